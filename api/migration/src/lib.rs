@@ -1,12 +1,48 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20261017_000002_add_message_edit_state;
+mod m20261017_000003_add_message_parent;
+mod m20261017_000004_create_reaction_table;
+mod m20261017_000005_create_direct_chat_table;
+mod m20261017_000006_create_chat_member_table;
+mod m20261017_000007_add_room_moderation;
+mod m20261017_000008_create_read_receipt_table;
+mod m20261017_000009_add_message_search_vector;
+mod m20261017_000010_add_chat_name_trigram_index;
+mod m20261017_000011_create_notification_table;
+mod m20261017_000012_drop_online_user_table;
+mod m20261017_000013_add_user_status;
+mod m20261017_000014_add_user_profile;
+mod m20261017_000015_create_refresh_token_table;
+mod m20261017_000016_add_username_unique_index;
+mod m20261017_000017_add_user_email;
+mod m20261017_000018_create_password_reset_token_table;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20261017_000002_add_message_edit_state::Migration),
+            Box::new(m20261017_000003_add_message_parent::Migration),
+            Box::new(m20261017_000004_create_reaction_table::Migration),
+            Box::new(m20261017_000005_create_direct_chat_table::Migration),
+            Box::new(m20261017_000006_create_chat_member_table::Migration),
+            Box::new(m20261017_000007_add_room_moderation::Migration),
+            Box::new(m20261017_000008_create_read_receipt_table::Migration),
+            Box::new(m20261017_000009_add_message_search_vector::Migration),
+            Box::new(m20261017_000010_add_chat_name_trigram_index::Migration),
+            Box::new(m20261017_000011_create_notification_table::Migration),
+            Box::new(m20261017_000012_drop_online_user_table::Migration),
+            Box::new(m20261017_000013_add_user_status::Migration),
+            Box::new(m20261017_000014_add_user_profile::Migration),
+            Box::new(m20261017_000015_create_refresh_token_table::Migration),
+            Box::new(m20261017_000016_add_username_unique_index::Migration),
+            Box::new(m20261017_000017_add_user_email::Migration),
+            Box::new(m20261017_000018_create_password_reset_token_table::Migration),
+        ]
     }
 }
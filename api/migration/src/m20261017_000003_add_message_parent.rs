@@ -0,0 +1,66 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Message::Table)
+                    .add_column(ColumnDef::new(Message::ParentId).integer().null())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk-message-parent_id-message-id")
+                            .from_tbl(Message::Table)
+                            .from_col(Message::ParentId)
+                            .to_tbl(Message::Table)
+                            .to_col(Message::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-message-parent_id")
+                    .table(Message::Table)
+                    .col(Message::ParentId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-message-parent_id")
+                    .table(Message::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Message::Table)
+                    .drop_foreign_key(Alias::new("fk-message-parent_id-message-id"))
+                    .drop_column(Message::ParentId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Message {
+    Table,
+    Id,
+    ParentId,
+}
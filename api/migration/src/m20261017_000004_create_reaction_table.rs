@@ -0,0 +1,86 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::schema::{pk_auto, string};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Reaction::Table)
+                    .if_not_exists()
+                    .col(pk_auto(Reaction::Id))
+                    .col(ColumnDef::new(Reaction::MessageId).integer().not_null())
+                    .col(ColumnDef::new(Reaction::UserId).integer().not_null())
+                    .col(string(Reaction::Emoji).not_null())
+                    .col(
+                        ColumnDef::new(Reaction::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-reaction-message_id-message-id")
+                            .from(Reaction::Table, Reaction::MessageId)
+                            .to(Message::Table, Message::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-reaction-user_id-user-id")
+                            .from(Reaction::Table, Reaction::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-reaction-message_id-user_id-emoji")
+                    .table(Reaction::Table)
+                    .col(Reaction::MessageId)
+                    .col(Reaction::UserId)
+                    .col(Reaction::Emoji)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Reaction::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Reaction {
+    Table,
+    Id,
+    MessageId,
+    UserId,
+    Emoji,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Message {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
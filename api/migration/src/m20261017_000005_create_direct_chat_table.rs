@@ -0,0 +1,113 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Chat::Table)
+                    .add_column(
+                        ColumnDef::new(Chat::IsDirect)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(DirectChat::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DirectChat::ChatId)
+                            .integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(DirectChat::UserLowId).integer().not_null())
+                    .col(ColumnDef::new(DirectChat::UserHighId).integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-direct_chat-chat_id-chat-id")
+                            .from(DirectChat::Table, DirectChat::ChatId)
+                            .to(Chat::Table, Chat::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-direct_chat-user_low_id-user-id")
+                            .from(DirectChat::Table, DirectChat::UserLowId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-direct_chat-user_high_id-user-id")
+                            .from(DirectChat::Table, DirectChat::UserHighId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // One conversation per pair of users; ids are stored lowest first.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-direct_chat-user_low_id-user_high_id")
+                    .table(DirectChat::Table)
+                    .col(DirectChat::UserLowId)
+                    .col(DirectChat::UserHighId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DirectChat::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Chat::Table)
+                    .drop_column(Chat::IsDirect)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Chat {
+    Table,
+    Id,
+    IsDirect,
+}
+
+#[derive(DeriveIden)]
+enum DirectChat {
+    Table,
+    ChatId,
+    UserLowId,
+    UserHighId,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
@@ -0,0 +1,119 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::schema::pk_auto;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Chat::Table)
+                    .add_column(
+                        ColumnDef::new(Chat::IsPrivate)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ChatMember::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ChatMember::Id))
+                    .col(ColumnDef::new(ChatMember::ChatId).integer().not_null())
+                    .col(ColumnDef::new(ChatMember::UserId).integer().not_null())
+                    .col(ColumnDef::new(ChatMember::InvitedBy).integer().null())
+                    .col(
+                        ColumnDef::new(ChatMember::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(ChatMember::JoinedAt).timestamp().null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-chat_member-chat_id-chat-id")
+                            .from(ChatMember::Table, ChatMember::ChatId)
+                            .to(Chat::Table, Chat::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-chat_member-user_id-user-id")
+                            .from(ChatMember::Table, ChatMember::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-chat_member-invited_by-user-id")
+                            .from(ChatMember::Table, ChatMember::InvitedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-chat_member-chat_id-user_id")
+                    .table(ChatMember::Table)
+                    .col(ChatMember::ChatId)
+                    .col(ChatMember::UserId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ChatMember::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Chat::Table)
+                    .drop_column(Chat::IsPrivate)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Chat {
+    Table,
+    Id,
+    IsPrivate,
+}
+
+#[derive(DeriveIden)]
+enum ChatMember {
+    Table,
+    Id,
+    ChatId,
+    UserId,
+    InvitedBy,
+    CreatedAt,
+    JoinedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
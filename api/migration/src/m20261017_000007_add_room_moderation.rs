@@ -0,0 +1,190 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::schema::pk_auto;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ChatMember::Table)
+                    .add_column(
+                        ColumnDef::new(ChatMember::Role)
+                            .string_len(16)
+                            .not_null()
+                            .default("member"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ChatSanction::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ChatSanction::Id))
+                    .col(ColumnDef::new(ChatSanction::ChatId).integer().not_null())
+                    .col(ColumnDef::new(ChatSanction::UserId).integer().not_null())
+                    .col(ColumnDef::new(ChatSanction::Kind).string_len(16).not_null())
+                    .col(ColumnDef::new(ChatSanction::ExpiresAt).timestamp().null())
+                    .col(ColumnDef::new(ChatSanction::CreatedBy).integer().null())
+                    .col(
+                        ColumnDef::new(ChatSanction::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-chat_sanction-chat_id-chat-id")
+                            .from(ChatSanction::Table, ChatSanction::ChatId)
+                            .to(Chat::Table, Chat::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-chat_sanction-user_id-user-id")
+                            .from(ChatSanction::Table, ChatSanction::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-chat_sanction-created_by-user-id")
+                            .from(ChatSanction::Table, ChatSanction::CreatedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-chat_sanction-chat_id-user_id-kind")
+                    .table(ChatSanction::Table)
+                    .col(ChatSanction::ChatId)
+                    .col(ChatSanction::UserId)
+                    .col(ChatSanction::Kind)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ModerationLog::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ModerationLog::Id))
+                    .col(ColumnDef::new(ModerationLog::ChatId).integer().not_null())
+                    .col(ColumnDef::new(ModerationLog::ActorId).integer().null())
+                    .col(ColumnDef::new(ModerationLog::TargetId).integer().not_null())
+                    .col(ColumnDef::new(ModerationLog::Action).string_len(16).not_null())
+                    .col(ColumnDef::new(ModerationLog::Reason).text().null())
+                    .col(ColumnDef::new(ModerationLog::ExpiresAt).timestamp().null())
+                    .col(
+                        ColumnDef::new(ModerationLog::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-moderation_log-chat_id-chat-id")
+                            .from(ModerationLog::Table, ModerationLog::ChatId)
+                            .to(Chat::Table, Chat::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-moderation_log-actor_id-user-id")
+                            .from(ModerationLog::Table, ModerationLog::ActorId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-moderation_log-target_id-user-id")
+                            .from(ModerationLog::Table, ModerationLog::TargetId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ModerationLog::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(ChatSanction::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ChatMember::Table)
+                    .drop_column(ChatMember::Role)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Chat {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum ChatMember {
+    Table,
+    Role,
+}
+
+#[derive(DeriveIden)]
+enum ChatSanction {
+    Table,
+    Id,
+    ChatId,
+    UserId,
+    Kind,
+    ExpiresAt,
+    CreatedBy,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum ModerationLog {
+    Table,
+    Id,
+    ChatId,
+    ActorId,
+    TargetId,
+    Action,
+    Reason,
+    ExpiresAt,
+    CreatedAt,
+}
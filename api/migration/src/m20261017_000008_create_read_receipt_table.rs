@@ -0,0 +1,103 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::schema::pk_auto;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ReadReceipt::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ReadReceipt::Id))
+                    .col(ColumnDef::new(ReadReceipt::ChatId).integer().not_null())
+                    .col(ColumnDef::new(ReadReceipt::UserId).integer().not_null())
+                    .col(
+                        ColumnDef::new(ReadReceipt::LastReadMessageId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ReadReceipt::UpdatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-read_receipt-chat_id-chat-id")
+                            .from(ReadReceipt::Table, ReadReceipt::ChatId)
+                            .to(Chat::Table, Chat::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-read_receipt-user_id-user-id")
+                            .from(ReadReceipt::Table, ReadReceipt::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-read_receipt-last_read_message_id-message-id")
+                            .from(ReadReceipt::Table, ReadReceipt::LastReadMessageId)
+                            .to(Message::Table, Message::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-read_receipt-chat_id-user_id")
+                    .table(ReadReceipt::Table)
+                    .col(ReadReceipt::ChatId)
+                    .col(ReadReceipt::UserId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ReadReceipt::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ReadReceipt {
+    Table,
+    Id,
+    ChatId,
+    UserId,
+    LastReadMessageId,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Chat {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Message {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
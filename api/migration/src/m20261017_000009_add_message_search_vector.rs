@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // A generated column keeps the vector in sync with edits without
+        // touching the write paths.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TABLE message ADD COLUMN search_vector tsvector \
+                 GENERATED ALWAYS AS (to_tsvector('english', content)) STORED",
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-message-search_vector")
+                    .table(Message::Table)
+                    .col(Message::SearchVector)
+                    .index_type(IndexType::Custom(SeaRc::new(Alias::new("GIN"))))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-message-search_vector")
+                    .table(Message::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Message::Table)
+                    .drop_column(Message::SearchVector)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Message {
+    Table,
+    SearchVector,
+}
@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared("CREATE EXTENSION IF NOT EXISTS pg_trgm")
+            .await?;
+        db.execute_unprepared(
+            "CREATE INDEX IF NOT EXISTS \"idx-chat-name-trgm\" \
+             ON chat USING GIN (name gin_trgm_ops)",
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The extension is left installed; other objects may depend on it.
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-chat-name-trgm")
+                    .table(Chat::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Chat {
+    Table,
+}
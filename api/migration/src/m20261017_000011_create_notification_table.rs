@@ -0,0 +1,112 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::schema::pk_auto;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Notification::Table)
+                    .if_not_exists()
+                    .col(pk_auto(Notification::Id))
+                    .col(ColumnDef::new(Notification::UserId).integer().not_null())
+                    .col(ColumnDef::new(Notification::Kind).string_len(16).not_null())
+                    .col(ColumnDef::new(Notification::ChatId).integer().not_null())
+                    .col(ColumnDef::new(Notification::MessageId).integer().not_null())
+                    .col(ColumnDef::new(Notification::ActorId).integer().null())
+                    .col(
+                        ColumnDef::new(Notification::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(Notification::ReadAt).timestamp().null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-notification-user_id-user-id")
+                            .from(Notification::Table, Notification::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-notification-chat_id-chat-id")
+                            .from(Notification::Table, Notification::ChatId)
+                            .to(Chat::Table, Chat::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-notification-message_id-message-id")
+                            .from(Notification::Table, Notification::MessageId)
+                            .to(Message::Table, Message::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-notification-actor_id-user-id")
+                            .from(Notification::Table, Notification::ActorId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-notification-user_id-read_at")
+                    .table(Notification::Table)
+                    .col(Notification::UserId)
+                    .col(Notification::ReadAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Notification::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Notification {
+    Table,
+    Id,
+    UserId,
+    Kind,
+    ChatId,
+    MessageId,
+    ActorId,
+    CreatedAt,
+    ReadAt,
+}
+
+#[derive(DeriveIden)]
+enum Chat {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Message {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
@@ -0,0 +1,73 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::schema::pk_auto;
+
+/// Presence moved to Redis, where entries expire on their own when an API
+/// process dies.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(OnlineUser::Table).to_owned())
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(OnlineUser::Table)
+                    .if_not_exists()
+                    .col(pk_auto(OnlineUser::Id))
+                    .col(ColumnDef::new(OnlineUser::UserId).integer().not_null())
+                    .col(ColumnDef::new(OnlineUser::ChatId).integer().not_null())
+                    .col(
+                        ColumnDef::new(OnlineUser::JoinedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-user-user_id-user-id")
+                            .from(OnlineUser::Table, OnlineUser::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-chat-chat_id-chat-id")
+                            .from(OnlineUser::Table, OnlineUser::ChatId)
+                            .to(Chat::Table, Chat::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Chat {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum OnlineUser {
+    Table,
+    Id,
+    UserId,
+    ChatId,
+    JoinedAt,
+}
@@ -0,0 +1,44 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(ColumnDef::new(User::DisplayName).string_len(50).null())
+                    .add_column(ColumnDef::new(User::Bio).text().null())
+                    .add_column(ColumnDef::new(User::AvatarKey).string().null())
+                    .add_column(ColumnDef::new(User::AvatarUrl).string().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::DisplayName)
+                    .drop_column(User::Bio)
+                    .drop_column(User::AvatarKey)
+                    .drop_column(User::AvatarUrl)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    DisplayName,
+    Bio,
+    AvatarKey,
+    AvatarUrl,
+}
@@ -0,0 +1,68 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // Usernames that only differ in case already exist if registration was
+        // never checked. The oldest account keeps its name; the others get
+        // their id appended, and a counter after that should the result be
+        // taken too, so the index can be built.
+        db.execute_unprepared(
+            "DO $$ \
+             DECLARE \
+                 dup RECORD; \
+                 candidate TEXT; \
+                 attempt INT; \
+             BEGIN \
+                 FOR dup IN \
+                     SELECT u.id, u.username FROM \"user\" AS u \
+                     WHERE EXISTS ( \
+                         SELECT 1 FROM \"user\" AS o \
+                         WHERE lower(o.username) = lower(u.username) AND o.id < u.id \
+                     ) \
+                     ORDER BY u.id \
+                 LOOP \
+                     candidate := dup.username || '_' || dup.id; \
+                     attempt := 1; \
+                     WHILE EXISTS ( \
+                         SELECT 1 FROM \"user\" WHERE lower(username) = lower(candidate) \
+                     ) LOOP \
+                         attempt := attempt + 1; \
+                         candidate := dup.username || '_' || dup.id || '_' || attempt; \
+                     END LOOP; \
+                     UPDATE \"user\" SET username = candidate WHERE id = dup.id; \
+                 END LOOP; \
+             END $$",
+        )
+        .await?;
+        db.execute_unprepared(
+            "CREATE UNIQUE INDEX IF NOT EXISTS \"idx-user-username-lower\" \
+             ON \"user\" (lower(username))",
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Renamed duplicates keep their new names.
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-user-username-lower")
+                    .table(User::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+}
@@ -0,0 +1,127 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use async_trait::async_trait;
+
+use crate::errors::Error;
+
+/// Somewhere to keep user uploads. Keys are `/`-separated relative paths such
+/// as `avatars/12-1760659200000.png`.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Stores the blob under `key`, replacing any previous one, and returns
+    /// the URL clients fetch it from.
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<String, Error>;
+
+    /// The blob under `key`, if there is one.
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Deleting a missing blob is not an error.
+    async fn delete(&self, key: &str) -> Result<(), Error>;
+}
+
+/// Keeps blobs as files under `root` and serves them through the API at
+/// `{public_url}/{key}`.
+pub struct LocalBlobStore {
+    root: PathBuf,
+    public_url: String,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>, public_url: String) -> Self {
+        LocalBlobStore {
+            root: root.into(),
+            public_url: public_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, Error> {
+        if !is_valid_key(key) {
+            return Err(Error::BadRequest("invalid blob key"));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<String, Error> {
+        let path = self.path(key)?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(&path, bytes).await?;
+        Ok(format!("{}/{key}", self.public_url))
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Keys may not climb out of the store: every segment is a plain, non-hidden
+/// file or directory name.
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.split('/').all(|segment| {
+            !segment.is_empty()
+                && !segment.starts_with('.')
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_plain_relative_keys() {
+        assert!(is_valid_key("avatars/42/3f2a-b_c.png"));
+        assert!(is_valid_key("file"));
+        assert!(is_valid_key("a/b.c.d"));
+    }
+
+    #[test]
+    fn rejects_keys_that_climb_out_of_the_store() {
+        for key in [
+            "..",
+            "../etc/passwd",
+            "avatars/../../secret",
+            "avatars/..",
+            "./avatars",
+            "/etc/passwd",
+            "avatars//1.png",
+            "avatars/1.png/",
+            "avatars\\..\\secret",
+        ] {
+            assert!(!is_valid_key(key), "{key:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_hidden_empty_and_unusual_names() {
+        for key in [
+            "",
+            ".env",
+            "avatars/.hidden",
+            "a b",
+            "ä.png",
+            "a%2F..",
+            "a\0b",
+        ] {
+            assert!(!is_valid_key(key), "{key:?}");
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, decode_header, encode,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+};
+use serde::{Serialize, de::DeserializeOwned};
+use spki::{ObjectIdentifier, SubjectPublicKeyInfoRef, der::Decode};
+
+use crate::{config::Settings, errors::Error};
+
+// Access tokens are signed with one private key and verified with any of the
+// public keys in `JWT_KEYS_DIR`, picked by the `kid` in the token header. The
+// directory holds `{kid}.pub.pem` for every key that is still honoured and
+// `{kid}.pem` for the one named by `JWT_SIGNING_KID`. The public keys are
+// published at `/.well-known/jwks.json` so the load balancer can verify
+// tokens without sharing a secret; the README describes how to rotate them.
+//
+// Without `JWT_KEYS_DIR` tokens are signed with the shared `JWT_SECRET` using
+// HS256 and nothing is published.
+
+const PUBLIC_KEY_SUFFIX: &str = ".pub.pem";
+const RSA_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const ED25519_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+
+struct VerificationKey {
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+pub struct JwtKeys {
+    signing_kid: Option<String>,
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    /// By `kid`. Tokens without one are checked against the shared secret,
+    /// if that is what we sign with.
+    verification_keys: HashMap<String, VerificationKey>,
+    secret_key: Option<VerificationKey>,
+    jwks: JwkSet,
+}
+
+impl JwtKeys {
+    pub fn from_settings(settings: &Settings) -> Result<Self, Box<dyn std::error::Error>> {
+        match &settings.jwt_keys_dir {
+            Some(dir) => {
+                let signing_kid = settings
+                    .jwt_signing_kid
+                    .as_deref()
+                    .ok_or("JWT_SIGNING_KID must be set when JWT_KEYS_DIR is")?;
+                Self::load(Path::new(dir), signing_kid)
+            }
+            None => Ok(Self::from_secret(&settings.jwt_secret)),
+        }
+    }
+
+    fn from_secret(secret: &str) -> Self {
+        Self {
+            signing_kid: None,
+            algorithm: Algorithm::HS256,
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            verification_keys: HashMap::new(),
+            secret_key: Some(VerificationKey {
+                algorithm: Algorithm::HS256,
+                key: DecodingKey::from_secret(secret.as_bytes()),
+            }),
+            jwks: JwkSet { keys: Vec::new() },
+        }
+    }
+
+    fn load(dir: &Path, signing_kid: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut verification_keys = HashMap::new();
+        let mut jwks = JwkSet { keys: Vec::new() };
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let Some(kid) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(PUBLIC_KEY_SUFFIX))
+            else {
+                continue;
+            };
+
+            let (algorithm, parameters) = parse_public_key(&fs::read(&path)?)
+                .map_err(|e| format!("invalid public key {}: {e}", path.display()))?;
+            let jwk = Jwk {
+                common: CommonParameters {
+                    public_key_use: Some(PublicKeyUse::Signature),
+                    key_algorithm: Some(key_algorithm(algorithm)),
+                    key_id: Some(kid.to_string()),
+                    ..Default::default()
+                },
+                algorithm: parameters,
+            };
+
+            verification_keys.insert(
+                kid.to_string(),
+                VerificationKey {
+                    algorithm,
+                    key: DecodingKey::from_jwk(&jwk)?,
+                },
+            );
+            jwks.keys.push(jwk);
+        }
+        jwks.keys
+            .sort_by(|a, b| a.common.key_id.cmp(&b.common.key_id));
+
+        let algorithm = verification_keys
+            .get(signing_kid)
+            .map(|key| key.algorithm)
+            .ok_or_else(|| format!("no {signing_kid}{PUBLIC_KEY_SUFFIX} in JWT_KEYS_DIR"))?;
+        let private_pem = fs::read(dir.join(format!("{signing_kid}.pem")))?;
+        let encoding_key = match algorithm {
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(&private_pem)?,
+            _ => EncodingKey::from_rsa_pem(&private_pem)?,
+        };
+
+        Ok(Self {
+            signing_kid: Some(signing_kid.to_string()),
+            algorithm,
+            encoding_key,
+            verification_keys,
+            secret_key: None,
+            jwks,
+        })
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, Error> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.signing_kid.clone();
+        Ok(encode(&header, claims, &self.encoding_key)?)
+    }
+
+    /// Checks the token against the key its header names. The algorithm comes
+    /// from our key, never from the token.
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, Error> {
+        let header = decode_header(token)?;
+        let key = match &header.kid {
+            Some(kid) => self.verification_keys.get(kid),
+            None => self.secret_key.as_ref(),
+        }
+        .ok_or(Error::Unauthorized)?;
+
+        let data = decode::<T>(token, &key.key, &Validation::new(key.algorithm))?;
+        Ok(data.claims)
+    }
+
+    /// The public keys tokens may be signed with.
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+}
+
+/// Reads a PEM `PUBLIC KEY` (SubjectPublicKeyInfo) holding an RSA or Ed25519
+/// key.
+fn parse_public_key(
+    pem_bytes: &[u8],
+) -> Result<(Algorithm, AlgorithmParameters), Box<dyn std::error::Error>> {
+    let pem = pem::parse(pem_bytes)?;
+    let spki = SubjectPublicKeyInfoRef::from_der(pem.contents())?;
+    let key_bytes = spki.subject_public_key.raw_bytes();
+
+    if spki.algorithm.oid == RSA_OID {
+        let rsa = pkcs1::RsaPublicKey::try_from(key_bytes)?;
+        Ok((
+            Algorithm::RS256,
+            AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(rsa.modulus.as_bytes()),
+                e: URL_SAFE_NO_PAD.encode(rsa.public_exponent.as_bytes()),
+            }),
+        ))
+    } else if spki.algorithm.oid == ED25519_OID {
+        Ok((
+            Algorithm::EdDSA,
+            AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(key_bytes),
+            }),
+        ))
+    } else {
+        Err(format!(
+            "unsupported key type {}, use RSA or Ed25519",
+            spki.algorithm.oid
+        )
+        .into())
+    }
+}
+
+fn key_algorithm(algorithm: Algorithm) -> KeyAlgorithm {
+    match algorithm {
+        Algorithm::EdDSA => KeyAlgorithm::EdDSA,
+        _ => KeyAlgorithm::RS256,
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::errors::Error;
+
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers mail to users, e.g. password reset links.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), Error>;
+}
+
+/// For local runs and tests: nothing leaves the machine. Every mail is written
+/// to the outbox directory as one file. Bodies are never logged, since they
+/// carry live reset links.
+pub struct LocalMailer {
+    outbox: PathBuf,
+}
+
+impl LocalMailer {
+    pub fn new(outbox: impl Into<PathBuf>) -> Self {
+        LocalMailer {
+            outbox: outbox.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for LocalMailer {
+    async fn send(&self, mail: Mail) -> Result<(), Error> {
+        tokio::fs::create_dir_all(&self.outbox).await?;
+        // Sorts by time of sending.
+        let name = format!("{}-{}.eml", Utc::now().timestamp_millis(), Uuid::new_v4());
+        let path = self.outbox.join(name);
+        let message = format!(
+            "To: {}\nSubject: {}\n\n{}",
+            mail.to, mail.subject, mail.body
+        );
+        tokio::fs::write(&path, message).await?;
+        info!("mail \"{}\" written to {}", mail.subject, path.display());
+        Ok(())
+    }
+}
@@ -1,7 +1,13 @@
+mod blob_store;
+mod jwt_keys;
+mod mailer;
 mod ollama;
 mod redis;
 mod session;
 
+pub use blob_store::{BlobStore, LocalBlobStore, is_valid_key};
+pub use jwt_keys::JwtKeys;
+pub use mailer::{LocalMailer, Mail, Mailer};
 pub use ollama::{ChatMessage, OllamaClient};
-pub use redis::RedisClient;
-pub use session::SessionClient;
+pub use redis::{Lagged, RedisClient};
+pub use session::{SessionClient, hash_token, random_token};
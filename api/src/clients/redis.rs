@@ -1,20 +1,114 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, TryRecvError},
+    },
+    thread,
+    time::Duration,
+};
+
 use crate::errors::Error;
-use redis::{AsyncCommands, Client, aio::Connection, aio::ConnectionManager};
+use redis::{
+    AsyncCommands, Client, Connection, FromRedisValue, Msg, Script, Value,
+    aio::ConnectionManager,
+    streams::{StreamId, StreamMaxlen, StreamRangeReply, StreamReadOptions, StreamReadReply},
+};
+use tokio::sync::{Notify, broadcast, watch};
+use tracing::{error, warn};
+
+const HUB_CHANNEL_CAPACITY: usize = 256;
+const HUB_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// How long the hub blocks on a read before it looks for subscription
+/// changes, i.e. how late a new subscription can take effect.
+const HUB_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long `subscribe` waits for Redis to confirm a new subscription.
+const HUB_SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Streams are trimmed to about this many events, which bounds how far back a
+/// reconnecting client can be replayed.
+const STREAM_MAX_LEN: usize = 1000;
+const STREAM_READ_BLOCK_MS: usize = 1000;
+const STREAM_READ_COUNT: usize = 100;
+const STREAM_EVENT_FIELD: &str = "event";
 
 pub struct RedisClient {
-    client: Client,
     connection: ConnectionManager,
+    hub: PubSubHub,
+    streams: StreamHub,
 }
 
 impl RedisClient {
     pub async fn new(url: String) -> Result<Self, Error> {
         let client = Client::open(url)?;
         let connection = ConnectionManager::new(client.clone()).await?;
-        Ok(RedisClient { client, connection })
+        // Blocking reads would stall every other command pipelined on the
+        // shared connection, so the stream reader gets its own.
+        let reader = ConnectionManager::new(client.clone()).await?;
+        let hub = PubSubHub::start(client);
+        let streams = StreamHub::start(reader);
+        Ok(RedisClient {
+            connection,
+            hub,
+            streams,
+        })
+    }
+
+    /// Receives everything published on `channel` through the process-wide
+    /// subscriber connection, from the moment this returns.
+    pub async fn subscribe(&self, channel: &str) -> Result<Subscription, Error> {
+        self.hub.subscribe(channel).await
+    }
+
+    /// Appends an event to a stream and returns its id.
+    pub async fn xadd_event(&self, key: &str, payload: String) -> Result<String, Error> {
+        let mut connection = self.connection.clone();
+        Ok(connection
+            .xadd_maxlen(
+                key,
+                StreamMaxlen::Approx(STREAM_MAX_LEN),
+                "*",
+                &[(STREAM_EVENT_FIELD, payload)],
+            )
+            .await?)
     }
 
-    pub async fn get_async_connection(&self) -> Result<Connection, redis::RedisError> {
-        self.client.get_async_connection().await
+    /// Events appended to a stream after `after`, up to and including
+    /// `until`, tagged with their `eventId`.
+    pub async fn stream_range(
+        &self,
+        key: &str,
+        after: &str,
+        until: &str,
+    ) -> Result<Vec<String>, Error> {
+        let mut connection = self.connection.clone();
+        let reply: StreamRangeReply = connection.xrange(key, format!("({after}"), until).await?;
+        Ok(reply.ids.iter().filter_map(tag_stream_event).collect())
+    }
+
+    /// Whether events appended after `after` may have been trimmed, i.e. the
+    /// oldest event still in the stream is newer than it.
+    pub async fn stream_trimmed_after(&self, key: &str, after: &str) -> Result<bool, Error> {
+        let mut connection = self.connection.clone();
+        let oldest: StreamRangeReply = connection.xrange_count(key, "-", "+", 1).await?;
+        Ok(oldest
+            .ids
+            .first()
+            .is_some_and(|entry| parse_stream_id(&entry.id) > parse_stream_id(after)))
+    }
+
+    /// Receives every event appended to the stream from now on, tagged with
+    /// its `eventId`. Also returns the id of the last event that will not be
+    /// delivered, so callers can replay up to it without gaps or repeats.
+    pub async fn subscribe_stream(&self, key: &str) -> Result<(Subscription, String), Error> {
+        let mut connection = self.connection.clone();
+        let latest: StreamRangeReply = connection.xrevrange_count(key, "+", "-", 1).await?;
+        let latest_id = latest
+            .ids
+            .first()
+            .map_or_else(|| "0-0".to_string(), |entry| entry.id.clone());
+
+        Ok(self.streams.subscribe(key, latest_id))
     }
 
     pub async fn publish(&self, key: &str, payload: String) -> Result<(), Error> {
@@ -36,4 +130,515 @@ impl RedisClient {
         let mut connection = self.connection.clone();
         Ok(connection.lrange(key, start, stop).await?)
     }
+
+    pub async fn set_ex(&self, key: &str, value: String, seconds: usize) -> Result<(), Error> {
+        let mut connection = self.connection.clone();
+        Ok(connection.set_ex(key, value, seconds).await?)
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        let mut connection = self.connection.clone();
+        Ok(connection.get(key).await?)
+    }
+
+    pub async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>, Error> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut connection = self.connection.clone();
+        Ok(redis::cmd("MGET")
+            .arg(keys)
+            .query_async(&mut connection)
+            .await?)
+    }
+
+    pub async fn del(&self, key: &str) -> Result<(), Error> {
+        let mut connection = self.connection.clone();
+        Ok(connection.del(key).await?)
+    }
+
+    pub async fn expire(&self, key: &str, seconds: usize) -> Result<(), Error> {
+        let mut connection = self.connection.clone();
+        Ok(connection.expire(key, seconds).await?)
+    }
+
+    pub async fn incr(&self, key: &str) -> Result<i64, Error> {
+        let mut connection = self.connection.clone();
+        Ok(connection.incr(key, 1).await?)
+    }
+
+    /// Returns whether `member` was newly added rather than re-scored.
+    pub async fn zadd(&self, key: &str, member: &str, score: i64) -> Result<bool, Error> {
+        let mut connection = self.connection.clone();
+        let added: i64 = connection.zadd(key, member, score).await?;
+        Ok(added > 0)
+    }
+
+    /// Returns whether `member` was present.
+    pub async fn zrem(&self, key: &str, member: &str) -> Result<bool, Error> {
+        let mut connection = self.connection.clone();
+        let removed: i64 = connection.zrem(key, member).await?;
+        Ok(removed > 0)
+    }
+
+    pub async fn zcard(&self, key: &str) -> Result<u64, Error> {
+        let mut connection = self.connection.clone();
+        Ok(connection.zcard(key).await?)
+    }
+
+    /// Members scored between `min` and `max`, which may also be `-inf`,
+    /// `+inf` or exclusive `(` bounds.
+    pub async fn zrangebyscore(
+        &self,
+        key: &str,
+        min: &str,
+        max: &str,
+    ) -> Result<Vec<String>, Error> {
+        let mut connection = self.connection.clone();
+        Ok(connection.zrangebyscore(key, min, max).await?)
+    }
+
+    /// `zrangebyscore` over several keys in one round trip.
+    pub async fn zrangebyscore_many(
+        &self,
+        keys: &[String],
+        min: &str,
+        max: &str,
+    ) -> Result<Vec<Vec<String>>, Error> {
+        let mut pipe = redis::pipe();
+        for key in keys {
+            pipe.zrangebyscore(key, min, max);
+        }
+        let mut connection = self.connection.clone();
+        Ok(pipe.query_async(&mut connection).await?)
+    }
+
+    /// Runs a Lua script atomically. It is loaded by its hash, so sending the
+    /// same source every time costs nothing after the first call.
+    pub async fn eval(&self, script: &str, keys: &[String], args: &[String]) -> Result<i64, Error> {
+        let mut connection = self.connection.clone();
+        let script = Script::new(script);
+        let mut invocation = script.prepare_invoke();
+        for key in keys {
+            invocation.key(key);
+        }
+        for arg in args {
+            invocation.arg(arg);
+        }
+        Ok(invocation.invoke_async(&mut connection).await?)
+    }
+
+    pub async fn sadd(&self, key: &str, member: &str) -> Result<(), Error> {
+        let mut connection = self.connection.clone();
+        Ok(connection.sadd(key, member).await?)
+    }
+
+    pub async fn srem(&self, key: &str, member: &str) -> Result<(), Error> {
+        let mut connection = self.connection.clone();
+        Ok(connection.srem(key, member).await?)
+    }
+
+    pub async fn smembers(&self, key: &str) -> Result<Vec<String>, Error> {
+        let mut connection = self.connection.clone();
+        Ok(connection.smembers(key).await?)
+    }
+}
+
+type ChannelRegistry = Arc<Mutex<HashMap<String, LocalChannel>>>;
+
+struct LocalChannel {
+    sender: broadcast::Sender<String>,
+    subscribers: usize,
+    /// For streams, the id of the last event handed to `sender`.
+    cursor: Option<String>,
+    /// For pub/sub channels, set once Redis confirmed the subscription.
+    subscribed: Arc<watch::Sender<bool>>,
+}
+
+impl LocalChannel {
+    fn new(cursor: Option<String>) -> Self {
+        LocalChannel {
+            sender: broadcast::channel(HUB_CHANNEL_CAPACITY).0,
+            subscribers: 0,
+            cursor,
+            subscribed: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    fn subscribe(
+        &mut self,
+        channel: &str,
+        channels: &ChannelRegistry,
+        commands: Option<&mpsc::Sender<HubCommand>>,
+    ) -> Subscription {
+        self.subscribers += 1;
+        Subscription {
+            channel: channel.to_string(),
+            receiver: self.sender.subscribe(),
+            channels: channels.clone(),
+            commands: commands.cloned(),
+        }
+    }
+}
+
+/// A change to the channels the hub connection is subscribed to. Sent while
+/// holding the registry lock, so the commands arrive in the order the
+/// reference counts changed. A subscribe carries the flag to set once Redis
+/// confirms it.
+enum HubCommand {
+    Subscribe(String, Arc<watch::Sender<bool>>),
+    Unsubscribe(String),
+}
+
+/// One pub/sub connection per process, fanned out to local sockets through
+/// tokio broadcast channels.
+///
+/// Local interest is reference-counted: the hub subscribes to a channel when
+/// its first `Subscription` is taken and unsubscribes when the last one is
+/// dropped, so Redis only sends what some socket here is listening to.
+///
+/// The async `PubSub` in redis 0.23 cannot change its subscriptions while its
+/// message stream is being read, and dropping the stream loses whatever it
+/// had buffered. The hub therefore reads a blocking connection on its own
+/// thread, whose parser keeps partial replies across read timeouts, and sends
+/// subscription changes between reads. Lost connections are re-established
+/// and resubscribed in the background.
+///
+/// Redis replies to every SUBSCRIBE in order, so each reply confirms the
+/// oldest subscribe still waiting on that channel. `subscribe` only returns
+/// once its channel is confirmed; nothing published before then would reach
+/// it.
+struct PubSubHub {
+    channels: ChannelRegistry,
+    commands: mpsc::Sender<HubCommand>,
+}
+
+impl PubSubHub {
+    fn start(client: Client) -> Self {
+        let channels = ChannelRegistry::default();
+        let (commands, inbox) = mpsc::channel();
+
+        let registry = channels.clone();
+        thread::Builder::new()
+            .name("pubsub-hub".to_string())
+            .spawn(move || {
+                loop {
+                    match Self::listen(&client, &registry, &inbox) {
+                        Ok(()) => return,
+                        Err(e) => error!("pubsub hub connection failed: {e:?}"),
+                    }
+                    thread::sleep(HUB_RECONNECT_DELAY);
+                }
+            })
+            .expect("failed to spawn the pubsub hub thread");
+
+        PubSubHub { channels, commands }
+    }
+
+    /// Reads messages until the connection fails. Returns `Ok` only once the
+    /// hub itself is gone.
+    fn listen(
+        client: &Client,
+        channels: &ChannelRegistry,
+        inbox: &mpsc::Receiver<HubCommand>,
+    ) -> redis::RedisResult<()> {
+        let mut connection = client.get_connection()?;
+        connection.set_read_timeout(Some(HUB_POLL_INTERVAL))?;
+
+        // Queued commands describe changes the registry already reflects.
+        let mut unconfirmed: HashMap<String, VecDeque<Arc<watch::Sender<bool>>>> = {
+            let registry = channels.lock().unwrap_or_else(|e| e.into_inner());
+            while inbox.try_recv().is_ok() {}
+            registry
+                .iter()
+                .map(|(channel, local)| {
+                    (channel.clone(), VecDeque::from([local.subscribed.clone()]))
+                })
+                .collect()
+        };
+        if !unconfirmed.is_empty() {
+            let resubscribe: Vec<String> = unconfirmed.keys().cloned().collect();
+            send_command(&mut connection, "SUBSCRIBE", &resubscribe)?;
+        }
+
+        loop {
+            loop {
+                match inbox.try_recv() {
+                    Ok(HubCommand::Subscribe(channel, subscribed)) => {
+                        send_command(&mut connection, "SUBSCRIBE", std::slice::from_ref(&channel))?;
+                        unconfirmed
+                            .entry(channel)
+                            .or_default()
+                            .push_back(subscribed);
+                    }
+                    Ok(HubCommand::Unsubscribe(channel)) => {
+                        send_command(&mut connection, "UNSUBSCRIBE", &[channel])?
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+
+            // Replies to the commands above come through here too. Those to
+            // a subscribe confirm it; anything else that is not a message is
+            // skipped.
+            let value = match connection.recv_response() {
+                Ok(value) => value,
+                Err(e) if e.is_timeout() => continue,
+                Err(e) => return Err(e),
+            };
+            if let Some(channel) = subscribe_reply(&value) {
+                if let Some(waiting) = unconfirmed.get_mut(&channel) {
+                    if let Some(subscribed) = waiting.pop_front() {
+                        subscribed.send_replace(true);
+                    }
+                    if waiting.is_empty() {
+                        unconfirmed.remove(&channel);
+                    }
+                }
+                continue;
+            }
+            let Some(msg) = Msg::from_value(&value) else {
+                continue;
+            };
+            let channel = msg.get_channel_name();
+            let Ok(payload) = msg.get_payload::<String>() else {
+                error!("invalid payload on {channel}");
+                continue;
+            };
+
+            let registry = channels.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(local) = registry.get(channel) {
+                // Only fails when every receiver is gone, which the
+                // reference count already accounts for.
+                let _ = local.sender.send(payload);
+            }
+        }
+    }
+
+    async fn subscribe(&self, channel: &str) -> Result<Subscription, Error> {
+        let (subscription, mut subscribed) = {
+            let mut registry = self.channels.lock().unwrap_or_else(|e| e.into_inner());
+            let local = registry.entry(channel.to_string()).or_insert_with(|| {
+                let local = LocalChannel::new(None);
+                let _ = self.commands.send(HubCommand::Subscribe(
+                    channel.to_string(),
+                    local.subscribed.clone(),
+                ));
+                local
+            });
+            (
+                local.subscribe(channel, &self.channels, Some(&self.commands)),
+                local.subscribed.subscribe(),
+            )
+        };
+
+        // Dropping the subscription on failure releases the channel again.
+        match tokio::time::timeout(HUB_SUBSCRIBE_TIMEOUT, subscribed.wait_for(|done| *done)).await {
+            Ok(Ok(_)) => Ok(subscription),
+            _ => {
+                error!("subscribing to {channel} timed out");
+                Err(Error::Unavailable("live updates are unavailable"))
+            }
+        }
+    }
+}
+
+/// The channel a `subscribe` reply confirms.
+fn subscribe_reply(value: &Value) -> Option<String> {
+    let Value::Bulk(items) = value else {
+        return None;
+    };
+    match items.as_slice() {
+        [kind, channel, _] if String::from_redis_value(kind).ok()? == "subscribe" => {
+            String::from_redis_value(channel).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Writes a (un)subscribe command without waiting for its reply, which may
+/// arrive after messages already on the way.
+fn send_command(
+    connection: &mut Connection,
+    name: &str,
+    channels: &[String],
+) -> redis::RedisResult<()> {
+    connection.send_packed_command(&redis::cmd(name).arg(channels).get_packed_command())
+}
+
+/// Reads every stream with local subscribers through one blocking `XREAD`
+/// loop and fans the events out like `PubSubHub`. Each stream is read from
+/// its own cursor, so streams added while a read is blocked are picked up on
+/// the next round without losing events.
+struct StreamHub {
+    streams: ChannelRegistry,
+    wake: Arc<Notify>,
+}
+
+impl StreamHub {
+    fn start(mut connection: ConnectionManager) -> Self {
+        let streams = ChannelRegistry::default();
+        let wake = Arc::new(Notify::new());
+
+        let registry = streams.clone();
+        let idle = wake.clone();
+        tokio::spawn(async move {
+            let options = StreamReadOptions::default()
+                .block(STREAM_READ_BLOCK_MS)
+                .count(STREAM_READ_COUNT);
+
+            loop {
+                let (keys, cursors): (Vec<String>, Vec<String>) = {
+                    let registry = registry.lock().unwrap_or_else(|e| e.into_inner());
+                    registry
+                        .iter()
+                        .filter_map(|(key, local)| Some((key.clone(), local.cursor.clone()?)))
+                        .unzip()
+                };
+                if keys.is_empty() {
+                    idle.notified().await;
+                    continue;
+                }
+
+                let reply: StreamReadReply =
+                    match connection.xread_options(&keys, &cursors, &options).await {
+                        Ok(reply) => reply,
+                        Err(e) => {
+                            error!("stream read failed: {e:?}");
+                            tokio::time::sleep(HUB_RECONNECT_DELAY).await;
+                            continue;
+                        }
+                    };
+
+                let mut registry = registry.lock().unwrap_or_else(|e| e.into_inner());
+                for stream in reply.keys {
+                    let Some(local) = registry.get_mut(&stream.key) else {
+                        continue;
+                    };
+                    for entry in &stream.ids {
+                        local.cursor = Some(entry.id.clone());
+                        if let Some(event) = tag_stream_event(entry) {
+                            let _ = local.sender.send(event);
+                        }
+                    }
+                }
+            }
+        });
+
+        StreamHub { streams, wake }
+    }
+
+    fn subscribe(&self, key: &str, latest_id: String) -> (Subscription, String) {
+        let mut registry = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        let is_new = !registry.contains_key(key);
+        let local = registry
+            .entry(key.to_string())
+            .or_insert_with(|| LocalChannel::new(Some(latest_id)));
+        // Another socket may already be reading this stream further along.
+        let start_id = local.cursor.clone().unwrap_or_default();
+        let subscription = local.subscribe(key, &self.streams, None);
+
+        if is_new {
+            self.wake.notify_one();
+        }
+        (subscription, start_id)
+    }
+}
+
+/// The stored event JSON with its stream id added as `eventId`.
+fn tag_stream_event(entry: &StreamId) -> Option<String> {
+    let payload: String = entry.get(STREAM_EVENT_FIELD)?;
+    let mut event: serde_json::Value = serde_json::from_str(&payload).ok()?;
+    event
+        .as_object_mut()?
+        .insert("eventId".to_string(), entry.id.clone().into());
+    Some(event.to_string())
+}
+
+/// A stream id as its `(millis, sequence)` parts, which order like the ids.
+fn parse_stream_id(id: &str) -> Option<(u64, u64)> {
+    let (millis, sequence) = id.split_once('-')?;
+    Some((millis.parse().ok()?, sequence.parse().ok()?))
+}
+
+/// The number of payloads a subscriber fell behind on and missed.
+pub struct Lagged(pub u64);
+
+/// A local handle on a hub channel. Dropping it releases the channel.
+pub struct Subscription {
+    channel: String,
+    receiver: broadcast::Receiver<String>,
+    channels: ChannelRegistry,
+    /// Set for pub/sub channels, which the hub unsubscribes from once the
+    /// last handle is gone.
+    commands: Option<mpsc::Sender<HubCommand>>,
+}
+
+impl Subscription {
+    /// The next payload on the channel. Payloads a slow reader fell behind on
+    /// are skipped.
+    pub async fn recv(&mut self) -> Option<String> {
+        loop {
+            match self.recv_checked().await? {
+                Ok(payload) => return Some(payload),
+                Err(Lagged(skipped)) => {
+                    warn!("subscriber on {} skipped {skipped} messages", self.channel);
+                }
+            }
+        }
+    }
+
+    /// Like `recv`, but reports payloads the reader fell behind on instead
+    /// of skipping them silently.
+    pub async fn recv_checked(&mut self) -> Option<Result<String, Lagged>> {
+        match self.receiver.recv().await {
+            Ok(payload) => Some(Ok(payload)),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => Some(Err(Lagged(skipped))),
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut registry = self.channels.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(local) = registry.get_mut(&self.channel) {
+            local.subscribers -= 1;
+            if local.subscribers == 0 {
+                registry.remove(&self.channel);
+                if let Some(commands) = &self.commands {
+                    let _ = commands.send(HubCommand::Unsubscribe(self.channel.clone()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(kind: &str, channel: &str) -> Value {
+        Value::Bulk(vec![
+            Value::Data(kind.as_bytes().to_vec()),
+            Value::Data(channel.as_bytes().to_vec()),
+            Value::Int(1),
+        ])
+    }
+
+    #[test]
+    fn recognizes_subscribe_replies() {
+        assert_eq!(
+            subscribe_reply(&bulk("subscribe", "user:1")).as_deref(),
+            Some("user:1")
+        );
+    }
+
+    #[test]
+    fn ignores_other_pubsub_frames() {
+        assert_eq!(subscribe_reply(&bulk("unsubscribe", "user:1")), None);
+        assert_eq!(subscribe_reply(&bulk("message", "user:1")), None);
+        assert_eq!(subscribe_reply(&Value::Okay), None);
+    }
 }
@@ -1,45 +1,119 @@
+use std::{cmp::Reverse, sync::Arc};
+
 use hyper::StatusCode;
+use rand::RngCore;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    sea_query::Expr,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tower_cookies::{
     Cookie, Cookies,
-    cookie::{CookieBuilder, SameSite},
+    cookie::{CookieBuilder, SameSite, time},
 };
+use tracing::{error, warn};
+use uuid::Uuid;
 
 use crate::errors::Error;
-use axum::http::HeaderMap;
+use axum::http::{HeaderMap, header};
+
+use chrono::{Duration, NaiveDateTime, Utc};
+
+use jsonwebtoken::jwk::JwkSet;
 
-use chrono::{Duration, Utc};
+use crate::clients::{JwtKeys, RedisClient};
+use crate::entity::{refresh_token, user};
+use crate::models::{claims::Claims, messages::OutgoingMessage, session::SessionInfo};
 
-use jsonwebtoken::{EncodingKey, Header, encode};
+// Access tokens are JWTs that are checked on every request and expire
+// quickly; the refresh token is opaque, single-use and trades itself for a
+// new pair at `POST /refresh`. Each login starts a token family whose id is
+// the session id (`jti`), and the session lasts for as long as it keeps being
+// refreshed within `REFRESH_TOKEN_TTL_DAYS`.
 
-use crate::models::claims::Claims;
+const ACCESS_TOKEN_TTL_MINUTES: i64 = 15;
+const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+const ACCESS_COOKIE: &str = "session";
+const REFRESH_COOKIE: &str = "refresh";
+const OPAQUE_TOKEN_BYTES: usize = 32;
+/// How long a traded refresh token is still taken for a concurrent refresh
+/// rather than a replay. Tabs share the refresh cookie, so two of them may
+/// trade the same token at once.
+const REFRESH_GRACE_SECONDS: usize = 10;
+/// How long a refresh that lost the race waits for the winner to finish.
+const REFRESH_GRACE_WAIT: std::time::Duration = std::time::Duration::from_secs(2);
+const REFRESH_GRACE_POLL: std::time::Duration = std::time::Duration::from_millis(100);
+const MAX_USER_AGENT_CHARS: usize = 256;
+
+/// What the registry keeps about a session under `session:{jti}`. The key
+/// expires together with the session's current refresh token, so access
+/// tokens are only honoured while their session exists.
+#[derive(Serialize, Deserialize)]
+struct SessionRecord {
+    user_id: i32,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    created_at: NaiveDateTime,
+    expires_at: NaiveDateTime,
+}
 
 pub struct SessionClient {
-    jwt_secret: String,
+    jwt_keys: JwtKeys,
+    db: DatabaseConnection,
+    redis_client: Arc<RedisClient>,
+}
+
+fn session_key(jti: &str) -> String {
+    format!("session:{jti}")
+}
+
+/// The hash of the refresh token that replaced the one with this hash, kept
+/// for `REFRESH_GRACE_SECONDS`.
+fn refresh_successor_key(token_hash: &str) -> String {
+    format!("refresh_successor:{token_hash}")
+}
+
+/// Every session id issued to the user. Ids whose record expired are pruned
+/// when the set is read.
+fn user_sessions_key(user_id: i32) -> String {
+    format!("user_sessions:{user_id}")
 }
 
 impl SessionClient {
-    pub fn new(jwt_secret: String) -> Self {
-        Self { jwt_secret }
+    pub fn new(jwt_keys: JwtKeys, db: DatabaseConnection, redis_client: Arc<RedisClient>) -> Self {
+        Self {
+            jwt_keys,
+            db,
+            redis_client,
+        }
     }
 
-    pub fn create_jwt_token(&self, user_id: i32, username: &str) -> Result<String, Error> {
-        let expiration = Utc::now()
-            .checked_add_signed(Duration::hours(24))
-            .ok_or(Error::InternalServer)?
-            .timestamp() as usize;
-
+    pub fn create_jwt_token(
+        &self,
+        user_id: i32,
+        username: &str,
+        jti: &str,
+        expires_at: NaiveDateTime,
+    ) -> Result<String, Error> {
         let claims = Claims {
             sub: user_id,
             username: username.to_string(),
-            exp: expiration,
+            exp: expires_at.and_utc().timestamp() as usize,
+            jti: jti.to_string(),
         };
 
-        let token = encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(self.jwt_secret.as_bytes()),
-        )?;
-        Ok(token)
+        self.jwt_keys.sign(&claims)
+    }
+
+    /// The claims of an access token with a valid signature that has not
+    /// expired. Whether its session is still active is up to the caller.
+    pub fn verify_jwt_token(&self, token: &str) -> Result<Claims, Error> {
+        self.jwt_keys.verify(token)
+    }
+
+    pub fn jwks(&self) -> &JwkSet {
+        self.jwt_keys.jwks()
     }
 
     pub fn get_is_secure(&self, headers: &HeaderMap) -> bool {
@@ -57,36 +131,395 @@ impl SessionClient {
         headers: HeaderMap,
         jar: Cookies,
     ) -> Result<StatusCode, Error> {
-        let token = self.create_jwt_token(user_id, username)?;
-        let is_secure = self.get_is_secure(&headers);
+        let jti = Uuid::new_v4().to_string();
+        let created_at = Utc::now().naive_utc();
 
-        jar.add(
-            CookieBuilder::new("session", token)
-                .http_only(true)
-                .secure(is_secure)
-                .same_site(SameSite::Lax)
-                .path("/")
-                .build(),
-        );
+        let (refresh_token, expires_at) = self.issue_refresh_token(user_id, &jti).await?;
+        self.register(
+            &jti,
+            SessionRecord {
+                user_id,
+                user_agent: user_agent(&headers),
+                ip: client_ip(&headers),
+                created_at,
+                expires_at,
+            },
+        )
+        .await?;
+
+        self.set_cookies(&jar, &headers, user_id, username, &jti, refresh_token)?;
+        Ok(StatusCode::OK)
+    }
 
+    /// Trades the refresh token cookie for a new access and refresh token.
+    /// Presenting a refresh token that was already traded means it leaked, so
+    /// the whole family, and with it the session, is revoked. The exception is
+    /// a token traded in the last `REFRESH_GRACE_SECONDS`, which is another tab
+    /// refreshing at the same time. That request gets no tokens of its own:
+    /// it answers `204 No Content` once the winner has issued the successor,
+    /// whose cookies the browser shares with every tab.
+    pub async fn refresh_session(
+        &self,
+        jar: &Cookies,
+        headers: &HeaderMap,
+    ) -> Result<StatusCode, Error> {
+        let token = jar
+            .get(REFRESH_COOKIE)
+            .map(|cookie| cookie.value().to_string())
+            .ok_or(Error::Unauthorized)?;
+        let token_hash = hash_token(&token);
+
+        let row = refresh_token::Entity::find()
+            .filter(refresh_token::Column::TokenHash.eq(&token_hash))
+            .one(&self.db)
+            .await?
+            .ok_or(Error::Unauthorized)?;
+
+        let now = Utc::now().naive_utc();
+        if row.revoked_at.is_some() || row.expires_at <= now {
+            return Err(Error::Unauthorized);
+        }
+
+        // Claiming the token and checking it was unused is one statement, so
+        // two concurrent refreshes cannot both succeed.
+        let claimed = refresh_token::Entity::update_many()
+            .col_expr(refresh_token::Column::UsedAt, Expr::value(now))
+            .filter(refresh_token::Column::Id.eq(row.id))
+            .filter(refresh_token::Column::UsedAt.is_null())
+            .exec(&self.db)
+            .await?;
+
+        if claimed.rows_affected == 0 {
+            if !self.wait_for_successor(&row, &token_hash).await? {
+                warn!(
+                    "refresh token reuse for user {} in family {}, revoking it",
+                    row.user_id, row.family_id
+                );
+                self.revoke(row.user_id, &row.family_id).await?;
+                self.clear_cookies(jar, headers);
+                return Err(Error::Unauthorized);
+            }
+            if !self.is_active(&row.family_id).await? {
+                return Err(Error::Unauthorized);
+            }
+            return Ok(StatusCode::NO_CONTENT);
+        }
+
+        if !self.is_active(&row.family_id).await? {
+            return Err(Error::Unauthorized);
+        }
+        let user_row = user::Entity::find_by_id(row.user_id)
+            .one(&self.db)
+            .await?
+            .ok_or(Error::Unauthorized)?;
+
+        let (refresh_token, expires_at) = self
+            .issue_refresh_token(user_row.id, &row.family_id)
+            .await?;
+        self.extend(&row.family_id, expires_at).await?;
+        self.redis_client
+            .set_ex(
+                &refresh_successor_key(&token_hash),
+                hash_token(&refresh_token),
+                REFRESH_GRACE_SECONDS,
+            )
+            .await?;
+
+        self.set_cookies(
+            jar,
+            headers,
+            user_row.id,
+            &user_row.username,
+            &row.family_id,
+            refresh_token,
+        )?;
         Ok(StatusCode::OK)
     }
 
+    /// Whether `row` was traded within the grace period by a refresh that
+    /// went on to issue its successor. That refresh may still be issuing it,
+    /// so this waits for it for a moment.
+    async fn wait_for_successor(
+        &self,
+        row: &refresh_token::Model,
+        token_hash: &str,
+    ) -> Result<bool, Error> {
+        let used_at = refresh_token::Entity::find_by_id(row.id)
+            .one(&self.db)
+            .await?
+            .and_then(|row| row.used_at);
+        let within_grace = used_at.is_some_and(|used_at| {
+            Utc::now().naive_utc() - used_at < Duration::seconds(REFRESH_GRACE_SECONDS as i64)
+        });
+        if !within_grace {
+            return Ok(false);
+        }
+
+        let key = refresh_successor_key(token_hash);
+        let deadline = tokio::time::Instant::now() + REFRESH_GRACE_WAIT;
+        loop {
+            if self.redis_client.get(&key).await?.is_some() {
+                return Ok(true);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Ok(false);
+            }
+            tokio::time::sleep(REFRESH_GRACE_POLL).await;
+        }
+    }
+
+    /// Revokes the session the request was made with and clears its cookies.
     pub async fn destroy_session(
         &self,
+        claims: &Claims,
         jar: &Cookies,
         headers: &HeaderMap,
     ) -> Result<StatusCode, Error> {
+        self.revoke(claims.sub, &claims.jti).await?;
+        self.clear_cookies(jar, headers);
+        Ok(StatusCode::OK)
+    }
+
+    /// Revokes every session of the user, including the current one.
+    pub async fn destroy_all_sessions(
+        &self,
+        claims: &Claims,
+        jar: &Cookies,
+        headers: &HeaderMap,
+    ) -> Result<StatusCode, Error> {
+        self.revoke_all(claims.sub, None).await?;
+        self.clear_cookies(jar, headers);
+        Ok(StatusCode::OK)
+    }
+
+    /// Whether the token with this id has not expired or been revoked.
+    pub async fn is_active(&self, jti: &str) -> Result<bool, Error> {
+        Ok(self.redis_client.get(&session_key(jti)).await?.is_some())
+    }
+
+    /// The user's active sessions, newest first.
+    pub async fn list_sessions(
+        &self,
+        user_id: i32,
+        current_jti: &str,
+    ) -> Result<Vec<SessionInfo>, Error> {
+        let index = user_sessions_key(user_id);
+        let ids = self.redis_client.smembers(&index).await?;
+        let keys: Vec<String> = ids.iter().map(|id| session_key(id)).collect();
+        let records = self.redis_client.get_many(&keys).await?;
+
+        let mut sessions = Vec::new();
+        for (id, record) in ids.into_iter().zip(records) {
+            let Some(record) = record.and_then(|r| serde_json::from_str::<SessionRecord>(&r).ok())
+            else {
+                self.redis_client.srem(&index, &id).await?;
+                continue;
+            };
+            sessions.push(SessionInfo {
+                current: id == current_jti,
+                id,
+                user_agent: record.user_agent,
+                ip: record.ip,
+                created_at: record.created_at,
+                expires_at: record.expires_at,
+            });
+        }
+
+        sessions.sort_by_key(|session| Reverse(session.created_at));
+        Ok(sessions)
+    }
+
+    /// Ends the session: its access tokens stop being honoured and its
+    /// refresh tokens can no longer be traded.
+    pub async fn revoke(&self, user_id: i32, jti: &str) -> Result<(), Error> {
+        refresh_token::Entity::update_many()
+            .col_expr(
+                refresh_token::Column::RevokedAt,
+                Expr::value(Utc::now().naive_utc()),
+            )
+            .filter(refresh_token::Column::FamilyId.eq(jti))
+            .filter(refresh_token::Column::RevokedAt.is_null())
+            .exec(&self.db)
+            .await?;
+
+        self.redis_client.del(&session_key(jti)).await?;
+        self.redis_client
+            .srem(&user_sessions_key(user_id), jti)
+            .await?;
+        self.announce_revoked(user_id, jti).await;
+        Ok(())
+    }
+
+    /// Revokes all of the user's sessions except `keep`, if given.
+    pub async fn revoke_all(&self, user_id: i32, keep: Option<&str>) -> Result<(), Error> {
+        let mut unused_tokens = refresh_token::Entity::update_many()
+            .col_expr(
+                refresh_token::Column::RevokedAt,
+                Expr::value(Utc::now().naive_utc()),
+            )
+            .filter(refresh_token::Column::UserId.eq(user_id))
+            .filter(refresh_token::Column::RevokedAt.is_null());
+        if let Some(keep) = keep {
+            unused_tokens = unused_tokens.filter(refresh_token::Column::FamilyId.ne(keep));
+        }
+        unused_tokens.exec(&self.db).await?;
+
+        let ids = self
+            .redis_client
+            .smembers(&user_sessions_key(user_id))
+            .await?;
+        for id in ids.iter().filter(|id| Some(id.as_str()) != keep) {
+            self.redis_client.del(&session_key(id)).await?;
+            self.redis_client
+                .srem(&user_sessions_key(user_id), id)
+                .await?;
+            self.announce_revoked(user_id, id).await;
+        }
+        Ok(())
+    }
+
+    /// Tells the sockets opened with the session, on any instance, to close.
+    /// Their access token stops working right away, but an open socket never
+    /// presents it again.
+    async fn announce_revoked(&self, user_id: i32, jti: &str) {
+        let event = OutgoingMessage::SessionRevoked {
+            session_id: jti.to_string(),
+        };
+        let Ok(payload) = serde_json::to_string(&event) else {
+            return;
+        };
+        if let Err(e) = self
+            .redis_client
+            .publish(&format!("user:{user_id}"), payload)
+            .await
+        {
+            error!("failed to announce the end of session {jti}: {e:?}");
+        }
+    }
+
+    /// Stores a new refresh token for the family, hashed, and returns it with
+    /// its expiry.
+    async fn issue_refresh_token(
+        &self,
+        user_id: i32,
+        family_id: &str,
+    ) -> Result<(String, NaiveDateTime), Error> {
+        let token = random_token();
+        let expires_at = Utc::now().naive_utc() + Duration::days(REFRESH_TOKEN_TTL_DAYS);
+
+        refresh_token::ActiveModel {
+            user_id: Set(user_id),
+            family_id: Set(family_id.to_string()),
+            token_hash: Set(hash_token(&token)),
+            expires_at: Set(expires_at),
+            ..Default::default()
+        }
+        .insert(&self.db)
+        .await?;
+
+        Ok((token, expires_at))
+    }
+
+    async fn register(&self, jti: &str, record: SessionRecord) -> Result<(), Error> {
+        let ttl = (record.expires_at - Utc::now().naive_utc())
+            .num_seconds()
+            .max(1) as usize;
+        let index = user_sessions_key(record.user_id);
+
+        self.redis_client
+            .set_ex(
+                &session_key(jti),
+                serde_json::to_string(&record).map_err(|_| Error::InternalServer)?,
+                ttl,
+            )
+            .await?;
+        self.redis_client.sadd(&index, jti).await?;
+        // Kept for as long as its newest session.
+        self.redis_client.expire(&index, ttl).await
+    }
+
+    /// Keeps the session alive until its new refresh token expires.
+    async fn extend(&self, jti: &str, expires_at: NaiveDateTime) -> Result<(), Error> {
+        let Some(record) = self.redis_client.get(&session_key(jti)).await? else {
+            return Err(Error::Unauthorized);
+        };
+        let mut record: SessionRecord =
+            serde_json::from_str(&record).map_err(|_| Error::InternalServer)?;
+        record.expires_at = expires_at;
+        self.register(jti, record).await
+    }
+
+    fn set_cookies(
+        &self,
+        jar: &Cookies,
+        headers: &HeaderMap,
+        user_id: i32,
+        username: &str,
+        jti: &str,
+        refresh_token: String,
+    ) -> Result<(), Error> {
+        let access_expires_at =
+            Utc::now().naive_utc() + Duration::minutes(ACCESS_TOKEN_TTL_MINUTES);
+        let access_token = self.create_jwt_token(user_id, username, jti, access_expires_at)?;
         let is_secure = self.get_is_secure(headers);
 
-        let cookie = Cookie::build(("session", ""))
-            .http_only(true)
-            .secure(is_secure)
-            .same_site(SameSite::Lax)
-            .path("/")
-            .build();
+        jar.add(
+            CookieBuilder::new(ACCESS_COOKIE, access_token)
+                .http_only(true)
+                .secure(is_secure)
+                .same_site(SameSite::Lax)
+                .path("/")
+                .build(),
+        );
+        jar.add(
+            CookieBuilder::new(REFRESH_COOKIE, refresh_token)
+                .http_only(true)
+                .secure(is_secure)
+                .same_site(SameSite::Lax)
+                .path("/")
+                .max_age(time::Duration::days(REFRESH_TOKEN_TTL_DAYS))
+                .build(),
+        );
+        Ok(())
+    }
 
-        jar.remove(cookie);
-        Ok(StatusCode::OK)
+    fn clear_cookies(&self, jar: &Cookies, headers: &HeaderMap) {
+        let is_secure = self.get_is_secure(headers);
+
+        for name in [ACCESS_COOKIE, REFRESH_COOKIE] {
+            let cookie = Cookie::build((name, ""))
+                .http_only(true)
+                .secure(is_secure)
+                .same_site(SameSite::Lax)
+                .path("/")
+                .build();
+
+            jar.remove(cookie);
+        }
     }
 }
+
+/// A new opaque token for the client to hold, hex-encoded. Only its hash is
+/// stored.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; OPAQUE_TOKEN_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn user_agent(headers: &HeaderMap) -> Option<String> {
+    let agent = headers.get(header::USER_AGENT)?.to_str().ok()?;
+    Some(agent.chars().take(MAX_USER_AGENT_CHARS).collect())
+}
+
+/// The client address as reported by the load balancer: the first entry of
+/// `X-Forwarded-For`.
+fn client_ip(headers: &HeaderMap) -> Option<String> {
+    let forwarded = headers.get("x-forwarded-for")?.to_str().ok()?;
+    let ip = forwarded.split(',').next()?.trim();
+    (!ip.is_empty()).then(|| ip.to_string())
+}
@@ -11,14 +11,26 @@ pub struct Model {
     pub name: String,
     pub owner_id: i32,
     pub created_at: DateTime,
+    pub is_direct: bool,
+    pub is_private: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::chat_member::Entity")]
+    ChatMember,
+    #[sea_orm(has_many = "super::chat_sanction::Entity")]
+    ChatSanction,
+    #[sea_orm(has_one = "super::direct_chat::Entity")]
+    DirectChat,
     #[sea_orm(has_many = "super::message::Entity")]
     Message,
-    #[sea_orm(has_many = "super::online_user::Entity")]
-    OnlineUser,
+    #[sea_orm(has_many = "super::moderation_log::Entity")]
+    ModerationLog,
+    #[sea_orm(has_many = "super::notification::Entity")]
+    Notification,
+    #[sea_orm(has_many = "super::read_receipt::Entity")]
+    ReadReceipt,
     #[sea_orm(
         belongs_to = "super::user::Entity",
         from = "Column::OwnerId",
@@ -29,15 +41,45 @@ pub enum Relation {
     User,
 }
 
+impl Related<super::chat_member::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ChatMember.def()
+    }
+}
+
+impl Related<super::chat_sanction::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ChatSanction.def()
+    }
+}
+
+impl Related<super::direct_chat::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::DirectChat.def()
+    }
+}
+
 impl Related<super::message::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Message.def()
     }
 }
 
-impl Related<super::online_user::Entity> for Entity {
+impl Related<super::moderation_log::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ModerationLog.def()
+    }
+}
+
+impl Related<super::notification::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Notification.def()
+    }
+}
+
+impl Related<super::read_receipt::Entity> for Entity {
     fn to() -> RelationDef {
-        Relation::OnlineUser.def()
+        Relation::ReadReceipt.def()
     }
 }
 
@@ -13,6 +13,9 @@ pub struct Model {
     pub sender_id: i32,
     pub chat_id: i32,
     pub created_at: DateTime,
+    pub edited_at: Option<DateTime>,
+    pub deleted_at: Option<DateTime>,
+    pub parent_id: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -25,6 +28,20 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     Chat,
+    #[sea_orm(
+        belongs_to = "Entity",
+        from = "Column::ParentId",
+        to = "Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    SelfRef,
+    #[sea_orm(has_many = "super::notification::Entity")]
+    Notification,
+    #[sea_orm(has_many = "super::reaction::Entity")]
+    Reaction,
+    #[sea_orm(has_many = "super::read_receipt::Entity")]
+    ReadReceipt,
     #[sea_orm(
         belongs_to = "super::user::Entity",
         from = "Column::SenderId",
@@ -47,4 +64,22 @@ impl Related<super::user::Entity> for Entity {
     }
 }
 
+impl Related<super::notification::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Notification.def()
+    }
+}
+
+impl Related<super::reaction::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Reaction.def()
+    }
+}
+
+impl Related<super::read_receipt::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReadReceipt.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -3,6 +3,14 @@
 pub mod prelude;
 
 pub mod chat;
+pub mod chat_member;
+pub mod chat_sanction;
+pub mod direct_chat;
 pub mod message;
-pub mod online_user;
+pub mod moderation_log;
+pub mod notification;
+pub mod password_reset_token;
+pub mod reaction;
+pub mod read_receipt;
+pub mod refresh_token;
 pub mod user;
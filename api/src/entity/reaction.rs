@@ -4,25 +4,26 @@ use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
-#[sea_orm(table_name = "online_user")]
+#[sea_orm(table_name = "reaction")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
+    pub message_id: i32,
     pub user_id: i32,
-    pub chat_id: i32,
-    pub joined_at: DateTime,
+    pub emoji: String,
+    pub created_at: DateTime,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(
-        belongs_to = "super::chat::Entity",
-        from = "Column::ChatId",
-        to = "super::chat::Column::Id",
+        belongs_to = "super::message::Entity",
+        from = "Column::MessageId",
+        to = "super::message::Column::Id",
         on_update = "Cascade",
         on_delete = "Cascade"
     )]
-    Chat,
+    Message,
     #[sea_orm(
         belongs_to = "super::user::Entity",
         from = "Column::UserId",
@@ -33,9 +34,9 @@ pub enum Relation {
     User,
 }
 
-impl Related<super::chat::Entity> for Entity {
+impl Related<super::message::Entity> for Entity {
     fn to() -> RelationDef {
-        Relation::Chat.def()
+        Relation::Message.def()
     }
 }
 
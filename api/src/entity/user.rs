@@ -10,6 +10,31 @@ pub struct Model {
     pub id: i32,
     pub username: String,
     pub password: String,
+    pub status: UserStatus,
+    pub status_message: Option<String>,
+    pub last_seen_at: Option<DateTime>,
+    pub display_name: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub bio: Option<String>,
+    pub avatar_key: Option<String>,
+    pub avatar_url: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum UserStatus {
+    #[sea_orm(string_value = "online")]
+    Online,
+    #[sea_orm(string_value = "away")]
+    Away,
+    #[sea_orm(string_value = "busy")]
+    Busy,
+    #[sea_orm(string_value = "invisible")]
+    Invisible,
+    #[sea_orm(string_value = "offline")]
+    Offline,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -18,8 +43,16 @@ pub enum Relation {
     Chat,
     #[sea_orm(has_many = "super::message::Entity")]
     Message,
-    #[sea_orm(has_many = "super::online_user::Entity")]
-    OnlineUser,
+    #[sea_orm(has_many = "super::notification::Entity")]
+    Notification,
+    #[sea_orm(has_many = "super::password_reset_token::Entity")]
+    PasswordResetToken,
+    #[sea_orm(has_many = "super::reaction::Entity")]
+    Reaction,
+    #[sea_orm(has_many = "super::read_receipt::Entity")]
+    ReadReceipt,
+    #[sea_orm(has_many = "super::refresh_token::Entity")]
+    RefreshToken,
 }
 
 impl Related<super::chat::Entity> for Entity {
@@ -34,9 +67,33 @@ impl Related<super::message::Entity> for Entity {
     }
 }
 
-impl Related<super::online_user::Entity> for Entity {
+impl Related<super::notification::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Notification.def()
+    }
+}
+
+impl Related<super::password_reset_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PasswordResetToken.def()
+    }
+}
+
+impl Related<super::reaction::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Reaction.def()
+    }
+}
+
+impl Related<super::read_receipt::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReadReceipt.def()
+    }
+}
+
+impl Related<super::refresh_token::Entity> for Entity {
     fn to() -> RelationDef {
-        Relation::OnlineUser.def()
+        Relation::RefreshToken.def()
     }
 }
 
@@ -29,8 +29,19 @@ pub enum Error {
     #[from]
     Reqwest(reqwest::Error),
 
+    #[from]
+    Io(std::io::Error),
+
     NotFound,
     Unauthorized,
+    Forbidden,
+    BadRequest(&'static str),
+    /// The request body failed validation; one entry per offending field.
+    Validation(Vec<FieldError>),
+    /// The request clashes with existing data, e.g. a taken username.
+    Conflict(Vec<FieldError>),
+    /// A feature that is not configured on this instance.
+    Unavailable(&'static str),
     InternalServer,
 
     OpenAiApi(String),
@@ -38,17 +49,57 @@ pub enum Error {
     SugesstionUnavailable,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    /// Stable identifier for clients to branch on, e.g. `too_short`.
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &'static str, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            field,
+            code,
+            message: message.into(),
+        }
+    }
+}
+
 #[derive(Serialize)]
 struct ErrorBody {
     error: &'static str,
 }
 
+#[derive(Serialize)]
+struct FieldErrorBody {
+    error: &'static str,
+    fields: Vec<FieldError>,
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         let (status, msg) = match self {
             // 1) Domain-specific:
             Error::NotFound => (StatusCode::NOT_FOUND, "not found"),
             Error::Unauthorized => (StatusCode::UNAUTHORIZED, "invalid credentials"),
+            Error::Forbidden => (StatusCode::FORBIDDEN, "forbidden"),
+            Error::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
+            Error::Validation(fields) => {
+                let body = FieldErrorBody {
+                    error: "invalid input",
+                    fields,
+                };
+                return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+            }
+            Error::Conflict(fields) => {
+                let body = FieldErrorBody {
+                    error: "conflict",
+                    fields,
+                };
+                return (StatusCode::CONFLICT, Json(body)).into_response();
+            }
 
             // 2) Infrastructure errors—log their inner payloads:
             Error::Db(e) => {
@@ -75,6 +126,10 @@ impl IntoResponse for Error {
                 error!("reqwest error: {:?}", e);
                 (StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
             }
+            Error::Io(e) => {
+                error!("io error: {:?}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
+            }
             Error::OpenAiApi(e) => {
                 error!("openai api error: {:?}", e);
                 (StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
@@ -87,6 +142,7 @@ impl IntoResponse for Error {
                 error!("suggestion unavailable");
                 (StatusCode::SERVICE_UNAVAILABLE, "suggestion unavailable")
             }
+            Error::Unavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
             Error::InternalServer => (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong"),
         };
 
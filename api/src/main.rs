@@ -1,7 +1,13 @@
 use crate::{
-    clients::{OllamaClient, RedisClient, SessionClient},
+    clients::{
+        BlobStore, JwtKeys, LocalBlobStore, LocalMailer, Mailer, OllamaClient, RedisClient,
+        SessionClient,
+    },
     middleware::{require_lb_auth, require_user_auth},
-    routes::{health_router, protected_router, public_router, ws_router},
+    routes::{
+        health_router, protected_router, public_router, spawn_presence_reaper, well_known_router,
+        ws_router,
+    },
 };
 use axum::{
     Router,
@@ -34,6 +40,10 @@ pub struct AppState {
     pub session_client: Arc<SessionClient>,
     pub ollama_client: Arc<OllamaClient>,
     pub redis_client: Arc<RedisClient>,
+    pub blob_store: Arc<dyn BlobStore>,
+    /// Unset when there is nowhere to deliver mail; password resets are then
+    /// refused.
+    pub mailer: Option<Arc<dyn Mailer>>,
 }
 
 #[tokio::main]
@@ -46,18 +56,40 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let db = Database::connect(database_url).await?;
     let settings = config::Settings::new();
 
-    let jwt_secret = settings.jwt_secret.clone();
+    let jwt_keys = JwtKeys::from_settings(&settings)?;
+
+    let redis_client = Arc::new(RedisClient::new(settings.redis_url.clone()).await.unwrap());
+
+    let session_client = Arc::new(SessionClient::new(
+        jwt_keys,
+        db.clone(),
+        redis_client.clone(),
+    ));
 
     let state = AppState {
         db,
         settings: settings.clone(),
-        session_client: Arc::new(SessionClient::new(jwt_secret)),
+        session_client,
         ollama_client: Arc::new(OllamaClient::new(settings.ollama_url).unwrap()),
-        redis_client: Arc::new(RedisClient::new(settings.redis_url).await.unwrap()),
+        redis_client,
+        blob_store: Arc::new(LocalBlobStore::new(
+            settings.upload_dir,
+            settings.blob_public_url,
+        )),
+        mailer: settings
+            .mail_outbox_dir
+            .map(|dir| Arc::new(LocalMailer::new(dir)) as Arc<dyn Mailer>),
     };
 
+    if state.mailer.is_none() {
+        tracing::warn!("MAIL_OUTBOX_DIR is not set, password resets are disabled");
+    }
+
+    spawn_presence_reaper(state.clone());
+
     let public = public_router().layer(from_fn_with_state(state.clone(), require_lb_auth));
     let health = health_router();
+    let well_known = well_known_router();
 
     let protected = protected_router()
         .layer(from_fn_with_state(state.clone(), require_user_auth))
@@ -80,13 +112,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let cors = CorsLayer::new()
         .allow_origin(AllowOrigin::list(allowed_origins))
-        .allow_methods(vec![Method::OPTIONS, Method::GET, Method::POST])
+        .allow_methods(vec![
+            Method::OPTIONS,
+            Method::GET,
+            Method::POST,
+            Method::PATCH,
+        ])
         .allow_headers(vec![header::CONTENT_TYPE, header::COOKIE])
         .allow_credentials(true)
         .max_age(Duration::from_secs(3600));
 
     let app = Router::new()
         .merge(health)
+        .merge(well_known)
         .merge(api_v1)
         .merge(ws_route)
         .layer(CookieManagerLayer::new())
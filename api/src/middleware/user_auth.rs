@@ -1,5 +1,4 @@
 use axum::{body::Body, extract::State, http::Request, middleware::Next, response::Response};
-use jsonwebtoken::{DecodingKey, Validation, decode};
 use sea_orm::EntityTrait;
 use tower_cookies::Cookies;
 
@@ -16,16 +15,21 @@ pub async fn require_user_auth(
         .map(|cookie| cookie.value().to_string())
         .ok_or(Error::Unauthorized)?;
 
-    let token_data = match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(state.settings.jwt_secret.as_bytes()),
-        &Validation::default(),
-    ) {
-        Ok(data) => data,
+    let claims: Claims = match state.session_client.verify_jwt_token(&token) {
+        Ok(claims) => claims,
         Err(_) => return Err(Error::Unauthorized),
     };
 
-    let user_id = token_data.claims.sub as i32;
+    if !state
+        .session_client
+        .is_active(&claims.jti)
+        .await
+        .map_err(|_| Error::InternalServer)?
+    {
+        return Err(Error::Unauthorized);
+    }
+
+    let user_id = claims.sub;
 
     if user::Entity::find_by_id(user_id)
         .one(&state.db)
@@ -36,7 +40,7 @@ pub async fn require_user_auth(
         return Err(Error::Unauthorized);
     }
 
-    request.extensions_mut().insert(token_data.claims.clone());
+    request.extensions_mut().insert(claims);
     let res = next.run(request).await;
     Ok(res)
 }
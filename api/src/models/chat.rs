@@ -1,25 +1,84 @@
-use sea_orm::FromQueryResult;
+use sea_orm::{FromQueryResult, prelude::DateTime};
 use serde::{Deserialize, Serialize};
 
+use crate::{entity::chat_member::MemberRole, models::messages::MessagePayload};
+
 #[derive(Serialize, Deserialize, FromQueryResult)]
 pub struct Chat {
     pub id: i32,
     pub name: String,
+    /// Filled in from Redis presence after the query.
+    #[sea_orm(skip)]
     pub active_users: i64,
+    pub unread_count: i64,
 }
 
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateChatRequest {
     pub name: String,
-    pub owner_id: i32,
+    #[serde(default)]
+    pub is_private: bool,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize)]
+pub struct ChatSocketParams {
+    pub chat_id: i32,
+    /// Replays the room's events after this one before going live.
+    pub last_event_id: Option<String>,
+}
+
+#[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct PreviousMessage {
+pub struct InviteMemberRequest {
+    pub user_id: i32,
+}
+
+#[derive(Serialize, FromQueryResult)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMember {
+    pub user_id: i32,
     pub username: String,
-    pub content: String,
+    pub invited_by: Option<i32>,
+    pub joined_at: Option<DateTime>,
+    pub role: MemberRole,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModerationRequest {
+    pub user_id: i32,
+    pub reason: Option<String>,
+    /// Ban or mute length; permanent when omitted.
+    pub duration_secs: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct SetRoleRequest {
+    pub role: MemberRole,
+}
+
+#[derive(Debug, Serialize, FromQueryResult)]
+#[serde(rename_all = "camelCase")]
+pub struct Invitation {
+    pub chat_id: i32,
+    pub chat_name: String,
+    pub invited_by: Option<i32>,
+    pub created_at: DateTime,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenDirectChatRequest {
+    pub user_id: i32,
+}
+
+#[derive(Serialize, FromQueryResult)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectChat {
+    pub id: i32,
+    pub other_user_id: i32,
+    pub other_username: String,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -28,5 +87,61 @@ pub struct GetChatResponse {
     pub id: i32,
     pub name: String,
     pub owner_id: i32,
-    pub messages: Vec<PreviousMessage>,
+    pub messages: Vec<MessagePayload>,
+}
+
+#[derive(Deserialize)]
+pub struct ChatSearchQuery {
+    pub q: String,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct ChatSearchResponse {
+    pub chats: Vec<Chat>,
+    pub total: u64,
+}
+
+#[derive(Deserialize)]
+pub struct MessageSearchQuery {
+    pub q: String,
+    pub chat_id: Option<i32>,
+    pub from: Option<DateTime>,
+    pub before: Option<DateTime>,
+    pub limit: Option<u64>,
+}
+
+/// A search hit. `snippet` is the matching excerpt with hits wrapped in
+/// `<mark>` tags.
+#[derive(Serialize, FromQueryResult)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageSearchResult {
+    pub id: i32,
+    pub chat_id: i32,
+    pub chat_name: String,
+    pub sender_id: i32,
+    pub username: String,
+    pub snippet: String,
+    pub created_at: DateTime,
+}
+
+#[derive(Deserialize)]
+pub struct MessageHistoryQuery {
+    pub before: Option<i32>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageHistoryResponse {
+    pub messages: Vec<MessagePayload>,
+    pub next_cursor: Option<i32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadResponse {
+    pub parent: MessagePayload,
+    pub replies: Vec<MessagePayload>,
 }
@@ -5,4 +5,6 @@ pub struct Claims {
     pub sub: i32,
     pub username: String,
     pub exp: usize,
+    /// The session id, looked up in the session registry on every request.
+    pub jti: String,
 }
@@ -1,7 +1,24 @@
 use serde::{Deserialize, Serialize};
 
+use crate::errors::FieldError;
+
 #[derive(Deserialize, Serialize)]
 pub struct CreateUserRequest {
     pub username: String,
     pub password: String,
+    /// Needed to reset a forgotten password.
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UsernameQuery {
+    pub username: String,
+}
+
+#[derive(Serialize)]
+pub struct UsernameAvailability {
+    pub username: String,
+    pub available: bool,
+    pub errors: Vec<FieldError>,
 }
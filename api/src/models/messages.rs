@@ -1,19 +1,302 @@
+use sea_orm::{FromQueryResult, prelude::DateTime};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    entity::{moderation_log::ModerationAction, user::UserStatus},
+    models::{chat::Invitation, notification::NotificationPayload},
+};
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type")]
 pub enum IncomingMessage {
     #[serde(rename = "chat_message")]
-    ChatMessage { content: String },
+    ChatMessage {
+        content: String,
+        #[serde(default)]
+        reply_to: Option<i32>,
+    },
     #[serde(rename = "request_suggestion")]
     RequestSuggestion { current_input: String },
+    #[serde(rename = "edit_message")]
+    EditMessage { message_id: i32, content: String },
+    #[serde(rename = "delete_message")]
+    DeleteMessage { message_id: i32 },
+    #[serde(rename = "add_reaction")]
+    AddReaction { message_id: i32, emoji: String },
+    #[serde(rename = "remove_reaction")]
+    RemoveReaction { message_id: i32, emoji: String },
+    #[serde(rename = "typing")]
+    Typing,
+    #[serde(rename = "mark_read")]
+    MarkRead { message_id: i32 },
 }
 
-#[derive(Debug, Serialize)]
+/// Room membership frames on the multiplexed `/ws/rooms` socket.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum SubscriptionFrame {
+    #[serde(rename = "subscribe")]
+    Subscribe {
+        chat_id: i32,
+        #[serde(default)]
+        last_event_id: Option<String>,
+    },
+    #[serde(rename = "unsubscribe")]
+    Unsubscribe { chat_id: i32 },
+}
+
+/// Any `IncomingMessage` addressed to one of the socket's rooms.
+#[derive(Debug, Deserialize)]
+pub struct RoomScopedMessage {
+    pub chat_id: i32,
+    #[serde(flatten)]
+    pub message: IncomingMessage,
+}
+
+/// Frames on the per-user `/ws/me` socket.
+#[derive(Debug, Deserialize)]
 #[serde(tag = "type")]
+pub enum UserFrame {
+    #[serde(rename = "set_status")]
+    SetStatus {
+        status: UserStatus,
+        #[serde(default)]
+        status_message: Option<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum MultiplexedMessage {
+    Subscription(SubscriptionFrame),
+    Room(RoomScopedMessage),
+}
+
+/// A persisted chat message as it is broadcast to sockets, cached in the
+/// `chat_messages:{id}` Redis list and returned by the history endpoints.
+#[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+#[serde(rename_all = "camelCase")]
+pub struct MessagePayload {
+    pub id: i32,
+    pub chat_id: i32,
+    pub sender_id: i32,
+    pub username: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub avatar_url: Option<String>,
+    pub content: String,
+    pub created_at: DateTime,
+    #[serde(default)]
+    pub edited_at: Option<DateTime>,
+    #[serde(default)]
+    pub deleted_at: Option<DateTime>,
+    #[serde(default)]
+    pub parent_id: Option<i32>,
+    #[serde(default)]
+    pub reply_count: i64,
+    #[serde(default)]
+    #[sea_orm(skip)]
+    pub reactions: Vec<ReactionSummary>,
+}
+
+/// Reaction totals for one emoji on a message, from the point of view of the
+/// requesting user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionSummary {
+    pub emoji: String,
+    pub count: i64,
+    pub reacted: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemMessageKind {
+    Join,
+    Leave,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OnlineUserEntry {
+    pub id: i32,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub status: UserStatus,
+    pub status_message: Option<String>,
+    pub last_seen_at: Option<DateTime>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all_fields = "camelCase")]
 pub enum OutgoingMessage {
+    #[serde(rename = "message")]
+    Message(MessagePayload),
+    #[serde(rename = "system_message")]
+    SystemMessage {
+        subtype: SystemMessageKind,
+        chat_id: i32,
+        user_id: i32,
+        username: String,
+        content: String,
+        created_at: DateTime,
+    },
+    #[serde(rename = "user_list")]
+    UserList {
+        chat_id: i32,
+        users: Vec<OnlineUserEntry>,
+    },
+    #[serde(rename = "user_count")]
+    UserCount { chat_id: i32, content: u64 },
     #[serde(rename = "suggestion")]
-    Suggestion { text: String },
+    Suggestion { chat_id: i32, text: String },
     #[serde(rename = "suggestion_error")]
-    SuggestionError { error: String },
+    SuggestionError { chat_id: i32, error: String },
+    #[serde(rename = "message_edited")]
+    MessageEdited {
+        id: i32,
+        chat_id: i32,
+        content: String,
+        edited_at: DateTime,
+    },
+    #[serde(rename = "message_deleted")]
+    MessageDeleted {
+        id: i32,
+        chat_id: i32,
+        deleted_at: DateTime,
+    },
+    #[serde(rename = "reactions_updated")]
+    ReactionsUpdated {
+        message_id: i32,
+        chat_id: i32,
+        user_id: i32,
+        emoji: String,
+        added: bool,
+        reactions: Vec<ReactionCount>,
+    },
+    #[serde(rename = "member_removed")]
+    MemberRemoved {
+        chat_id: i32,
+        user_id: i32,
+        action: ModerationAction,
+        reason: Option<String>,
+    },
+    #[serde(rename = "member_mute_changed")]
+    MemberMuteChanged {
+        chat_id: i32,
+        user_id: i32,
+        muted: bool,
+        expires_at: Option<DateTime>,
+    },
+    #[serde(rename = "user_typing")]
+    UserTyping {
+        chat_id: i32,
+        user_id: i32,
+        username: String,
+        typing: bool,
+    },
+    #[serde(rename = "read_receipt")]
+    ReadReceipt {
+        chat_id: i32,
+        user_id: i32,
+        username: String,
+        message_id: i32,
+    },
+    #[serde(rename = "mention")]
+    Mention(NotificationPayload),
+    #[serde(rename = "direct_message")]
+    DirectMessage(MessagePayload),
+    #[serde(rename = "invitation")]
+    Invitation(Invitation),
+    #[serde(rename = "unread_count")]
+    UnreadCount { chat_id: i32, unread_count: u64 },
+    #[serde(rename = "error")]
+    Error { chat_id: Option<i32>, error: String },
+    /// `chat_id` is set on room streams and absent on the user's own channel,
+    /// which sees the status as chosen rather than as others see it.
+    #[serde(rename = "user_status")]
+    UserStatus {
+        chat_id: Option<i32>,
+        user_id: i32,
+        status: UserStatus,
+        status_message: Option<String>,
+    },
+    #[serde(rename = "subscribed")]
+    Subscribed { chat_id: i32 },
+    #[serde(rename = "unsubscribed")]
+    Unsubscribed { chat_id: i32 },
+    /// Some of the room's events could not be delivered, either because they
+    /// were trimmed from the stream before the socket resumed or because the
+    /// socket fell behind. The client should reload the room.
+    #[serde(rename = "resync")]
+    Resync { chat_id: i32 },
+    /// Sent on the user's channel when one of their sessions ends. Sockets
+    /// opened with it close themselves.
+    #[serde(rename = "session_revoked")]
+    SessionRevoked { session_id: String },
+}
+
+/// The user channel events a socket acts on itself.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all_fields = "camelCase")]
+pub enum UserControlEvent {
+    #[serde(rename = "session_revoked")]
+    SessionRevoked { session_id: String },
+    #[serde(other)]
+    Other,
+}
+
+impl UserControlEvent {
+    /// Whether `text` ends the session with this id.
+    pub fn revokes(text: &str, session_id: &str) -> bool {
+        matches!(
+            serde_json::from_str::<UserControlEvent>(text),
+            Ok(UserControlEvent::SessionRevoked { session_id: revoked }) if revoked == session_id
+        )
+    }
+}
+
+/// The room events a socket acts on itself instead of only forwarding them.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all_fields = "camelCase")]
+pub enum RoomControlEvent {
+    #[serde(rename = "member_removed")]
+    MemberRemoved { user_id: i32 },
+    #[serde(rename = "user_typing")]
+    UserTyping { user_id: i32 },
+    #[serde(other)]
+    Other,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_matching_session_is_revoked() {
+        let event = serde_json::to_string(&OutgoingMessage::SessionRevoked {
+            session_id: "abc".to_string(),
+        })
+        .unwrap();
+        assert!(UserControlEvent::revokes(&event, "abc"));
+        assert!(!UserControlEvent::revokes(&event, "abd"));
+    }
+
+    #[test]
+    fn other_user_events_revoke_nothing() {
+        assert!(!UserControlEvent::revokes(
+            r#"{"type":"mention","sessionId":"abc"}"#,
+            "abc"
+        ));
+        assert!(!UserControlEvent::revokes("not json", "abc"));
+    }
 }
@@ -3,4 +3,7 @@ pub mod claims;
 pub mod create_user;
 pub mod login;
 pub mod messages;
+pub mod notification;
+pub mod password;
+pub mod session;
 pub mod user;
@@ -0,0 +1,28 @@
+use sea_orm::{FromQueryResult, prelude::DateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::entity::notification::NotificationKind;
+
+#[derive(Deserialize)]
+pub struct NotificationQuery {
+    #[serde(default)]
+    pub unread: bool,
+    pub before: Option<i32>,
+    pub limit: Option<u64>,
+}
+
+/// A notification as listed in the inbox and pushed on the user's channel.
+#[derive(Debug, Serialize, Deserialize, FromQueryResult, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationPayload {
+    pub id: i32,
+    pub kind: NotificationKind,
+    pub chat_id: i32,
+    pub chat_name: String,
+    pub message_id: i32,
+    pub actor_id: Option<i32>,
+    pub actor_username: Option<String>,
+    pub content: String,
+    pub created_at: DateTime,
+    pub read_at: Option<DateTime>,
+}
@@ -0,0 +1,14 @@
+use sea_orm::prelude::DateTime;
+use serde::Serialize;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub id: String,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+    /// Whether this is the session the request was made with.
+    pub current: bool,
+}
@@ -1,7 +1,99 @@
+use sea_orm::prelude::DateTime;
 use serde::{Deserialize, Serialize};
 
+use crate::entity::user::{self, UserStatus};
+
 #[derive(Serialize, Deserialize)]
 pub struct UserResponse {
     pub id: i32,
     pub username: String,
 }
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetStatusRequest {
+    pub status: UserStatus,
+    #[serde(default)]
+    pub status_message: Option<String>,
+}
+
+/// Fields left out are unchanged; an empty string clears the field.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateProfileRequest {
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub email: Option<String>,
+    /// Required when `email` changes.
+    pub current_password: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserProfile {
+    pub id: i32,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub status: UserStatus,
+    pub status_message: Option<String>,
+    pub last_seen_at: Option<DateTime>,
+    /// Only in the user's own profile.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+impl UserProfile {
+    /// The profile as other users see it: invisible users, and users without a
+    /// live connection, appear offline and without a status message.
+    /// Invisible users do not show when they were last seen either.
+    pub fn public(user: user::Model, online: bool) -> Self {
+        let (status, status_message) = visible_status(&user, online);
+        let last_seen_at = visible_last_seen(&user);
+        UserProfile {
+            id: user.id,
+            username: user.username,
+            display_name: user.display_name,
+            avatar_url: user.avatar_url,
+            bio: user.bio,
+            status,
+            status_message,
+            last_seen_at,
+            email: None,
+        }
+    }
+
+    /// The profile as the user sees it, with the status they chose.
+    pub fn own(user: user::Model) -> Self {
+        UserProfile {
+            id: user.id,
+            username: user.username,
+            display_name: user.display_name,
+            avatar_url: user.avatar_url,
+            bio: user.bio,
+            status: user.status,
+            status_message: user.status_message,
+            last_seen_at: user.last_seen_at,
+            email: user.email,
+        }
+    }
+}
+
+/// The status and message others see for `user`.
+pub fn visible_status(user: &user::Model, online: bool) -> (UserStatus, Option<String>) {
+    if !online || user.status == UserStatus::Invisible {
+        (UserStatus::Offline, None)
+    } else {
+        (user.status, user.status_message.clone())
+    }
+}
+
+/// When others may see that `user` was last around.
+pub fn visible_last_seen(user: &user::Model) -> Option<DateTime> {
+    if user.status == UserStatus::Invisible {
+        None
+    } else {
+        user.last_seen_at
+    }
+}
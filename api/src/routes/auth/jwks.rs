@@ -0,0 +1,19 @@
+use axum::{
+    Json,
+    extract::State,
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+
+use crate::AppState;
+
+/// The public keys access tokens are signed with. Unauthenticated, so the load
+/// balancer and anyone else can verify tokens. Cached briefly so a rotation
+/// shows up within a minute.
+pub async fn jwks(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "public, max-age=60")],
+        Json(state.session_client.jwks().clone()),
+    )
+}
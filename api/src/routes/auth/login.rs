@@ -2,12 +2,14 @@ use axum::{Json, extract::State, http::HeaderMap};
 use bcrypt::verify;
 use hyper::StatusCode;
 
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use sea_orm::{EntityTrait, QueryFilter};
 use tower_cookies::Cookies;
 
 use crate::errors::Error;
 use crate::{AppState, entity::user, models::login::LoginPayload};
 
+use super::validation::username_eq;
+
 pub async fn login(
     jar: Cookies,
     State(state): State<AppState>,
@@ -15,7 +17,7 @@ pub async fn login(
     Json(payload): Json<LoginPayload>,
 ) -> Result<StatusCode, Error> {
     let user = user::Entity::find()
-        .filter(user::Column::Username.eq(payload.username))
+        .filter(username_eq(&payload.username))
         .one(&state.db)
         .await?
         .ok_or(Error::NotFound)?;
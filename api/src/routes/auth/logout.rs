@@ -1,13 +1,31 @@
-use crate::{AppState, errors::Error};
+use crate::{AppState, errors::Error, models::claims::Claims};
 use axum::http::HeaderMap;
-use axum::{extract::State, http::StatusCode};
+use axum::{Extension, extract::State, http::StatusCode};
 use tower_cookies::Cookies;
 
 pub async fn logout(
+    Extension(claims): Extension<Claims>,
     jar: Cookies,
     headers: HeaderMap,
     State(state): State<AppState>,
 ) -> Result<StatusCode, Error> {
-    state.session_client.destroy_session(&jar, &headers).await?;
+    state
+        .session_client
+        .destroy_session(&claims, &jar, &headers)
+        .await?;
+    Ok(StatusCode::OK)
+}
+
+/// Revokes every session of the user, on every device.
+pub async fn logout_everywhere(
+    Extension(claims): Extension<Claims>,
+    jar: Cookies,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<StatusCode, Error> {
+    state
+        .session_client
+        .destroy_all_sessions(&claims, &jar, &headers)
+        .await?;
     Ok(StatusCode::OK)
 }
@@ -1,9 +1,21 @@
+mod jwks;
 mod login;
 mod logout;
+mod password;
+mod refresh;
 mod register;
+mod sessions;
+mod validation;
 mod whoami;
 
+pub use jwks::jwks;
 pub use login::login;
-pub use logout::logout;
-pub use register::register;
+pub use logout::{logout, logout_everywhere};
+pub use password::{
+    change_password, forgot_password, invalidate_reset_tokens, reset_password, verify_password,
+};
+pub use refresh::refresh;
+pub use register::{register, username_available};
+pub use sessions::list_sessions;
+pub use validation::{check_email, email_taken_error, username_in};
 pub use whoami::whoami;
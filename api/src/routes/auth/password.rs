@@ -0,0 +1,219 @@
+use axum::{Extension, Json, extract::State, http::StatusCode};
+use bcrypt::{DEFAULT_COST, hash, verify};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, sea_query::Expr,
+};
+use tracing::error;
+
+use crate::{
+    AppState,
+    clients::{Mail, Mailer, hash_token, random_token},
+    entity::{password_reset_token, user},
+    errors::{Error, FieldError},
+    models::{
+        claims::Claims,
+        password::{ChangePasswordRequest, ForgotPasswordRequest, ResetPasswordRequest},
+    },
+};
+
+use super::validation::{check_password, email_eq};
+
+// A reset token is mailed to the address on the account and traded, once, for
+// a new password at `POST /password/reset`. Only its hash is stored, and asking
+// for a new one invalidates the ones before it. Setting a new password either
+// way ends the account's other sessions.
+
+const RESET_TOKEN_TTL_MINUTES: i64 = 30;
+
+/// Sets a new password after checking the current one. The session the
+/// request was made with stays signed in; all others are revoked.
+pub async fn change_password(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> Result<StatusCode, Error> {
+    let user_row = user::Entity::find_by_id(claims.sub)
+        .one(&state.db)
+        .await?
+        .ok_or(Error::Unauthorized)?;
+
+    // Not a 401: that would read as an expired session.
+    if !verify_password(&payload.current_password, &user_row.password).await? {
+        return Err(Error::Validation(vec![FieldError::new(
+            "currentPassword",
+            "incorrect",
+            "is not your current password",
+        )]));
+    }
+    if let Some(error) = check_password("newPassword", &payload.new_password, &user_row.username) {
+        return Err(Error::Validation(vec![error]));
+    }
+    if payload.new_password == payload.current_password {
+        return Err(Error::Validation(vec![FieldError::new(
+            "newPassword",
+            "unchanged",
+            "must differ from the current password",
+        )]));
+    }
+
+    set_password(&state, user_row.id, &payload.new_password).await?;
+    state
+        .session_client
+        .revoke_all(user_row.id, Some(&claims.jti))
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Mails a reset link to the account with this address, if there is one. The
+/// lookup and everything after it happen in the background and the answer is
+/// always the same, so neither it nor its timing tells whether the account
+/// exists.
+pub async fn forgot_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ForgotPasswordRequest>,
+) -> Result<StatusCode, Error> {
+    let mailer = state
+        .mailer
+        .clone()
+        .ok_or(Error::Unavailable("password reset is not available"))?;
+
+    tokio::spawn(async move {
+        let email = payload.email.trim();
+        if let Err(e) = send_reset_link(&state, mailer.as_ref(), email).await {
+            error!("failed to send password reset mail: {e:?}");
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn send_reset_link(state: &AppState, mailer: &dyn Mailer, email: &str) -> Result<(), Error> {
+    let Some(user_row) = user::Entity::find()
+        .filter(email_eq(email))
+        .one(&state.db)
+        .await?
+    else {
+        return Ok(());
+    };
+    let Some(address) = user_row.email.clone() else {
+        return Ok(());
+    };
+
+    invalidate_reset_tokens(state, user_row.id).await?;
+
+    let token = random_token();
+    password_reset_token::ActiveModel {
+        user_id: Set(user_row.id),
+        token_hash: Set(hash_token(&token)),
+        expires_at: Set(Utc::now().naive_utc() + Duration::minutes(RESET_TOKEN_TTL_MINUTES)),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await?;
+
+    mailer
+        .send(Mail {
+            to: address,
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Hi {},\n\n\
+                 Someone asked to reset the password of your account. Open this link \
+                 within {RESET_TOKEN_TTL_MINUTES} minutes to choose a new one:\n\n\
+                 {}?token={token}\n\n\
+                 If that wasn't you, ignore this mail and your password stays as it is.\n",
+                user_row.username, state.settings.password_reset_url,
+            ),
+        })
+        .await
+}
+
+/// Trades a reset token for a new password and signs the account out
+/// everywhere.
+pub async fn reset_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ResetPasswordRequest>,
+) -> Result<StatusCode, Error> {
+    let now = Utc::now().naive_utc();
+    let row = password_reset_token::Entity::find()
+        .filter(password_reset_token::Column::TokenHash.eq(hash_token(&payload.token)))
+        .one(&state.db)
+        .await?
+        .filter(|row| row.used_at.is_none() && row.expires_at > now)
+        .ok_or_else(invalid_token_error)?;
+
+    let user_row = user::Entity::find_by_id(row.user_id)
+        .one(&state.db)
+        .await?
+        .ok_or_else(invalid_token_error)?;
+
+    // Checked before the token is claimed, so a rejected password does not use
+    // it up.
+    if let Some(error) = check_password("newPassword", &payload.new_password, &user_row.username) {
+        return Err(Error::Validation(vec![error]));
+    }
+
+    // Claiming the token and checking it was unused is one statement, so two
+    // concurrent resets cannot both succeed.
+    let claimed = password_reset_token::Entity::update_many()
+        .col_expr(password_reset_token::Column::UsedAt, Expr::value(now))
+        .filter(password_reset_token::Column::Id.eq(row.id))
+        .filter(password_reset_token::Column::UsedAt.is_null())
+        .exec(&state.db)
+        .await?;
+    if claimed.rows_affected == 0 {
+        return Err(invalid_token_error());
+    }
+
+    set_password(&state, user_row.id, &payload.new_password).await?;
+    state.session_client.revoke_all(user_row.id, None).await?;
+
+    Ok(StatusCode::OK)
+}
+
+fn invalid_token_error() -> Error {
+    Error::Validation(vec![FieldError::new(
+        "token",
+        "invalid",
+        "this reset link is invalid or has expired",
+    )])
+}
+
+pub async fn verify_password(password: &str, password_hash: &str) -> Result<bool, Error> {
+    let password = password.to_string();
+    let password_hash = password_hash.to_string();
+    Ok(tokio::task::spawn_blocking(move || verify(&password, &password_hash)).await??)
+}
+
+/// Stores the new password and invalidates any outstanding reset tokens,
+/// which were issued for the old one.
+async fn set_password(state: &AppState, user_id: i32, password: &str) -> Result<(), Error> {
+    let password = password.to_string();
+    let hashed_pw = tokio::task::spawn_blocking(move || hash(&password, DEFAULT_COST)).await??;
+
+    user::ActiveModel {
+        id: Set(user_id),
+        password: Set(hashed_pw),
+        ..Default::default()
+    }
+    .update(&state.db)
+    .await?;
+
+    invalidate_reset_tokens(state, user_id).await
+}
+
+/// Reset tokens that were mailed out stop working, e.g. because the address
+/// they went to is no longer the account's.
+pub async fn invalidate_reset_tokens(state: &AppState, user_id: i32) -> Result<(), Error> {
+    password_reset_token::Entity::update_many()
+        .col_expr(
+            password_reset_token::Column::UsedAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .filter(password_reset_token::Column::UserId.eq(user_id))
+        .filter(password_reset_token::Column::UsedAt.is_null())
+        .exec(&state.db)
+        .await?;
+    Ok(())
+}
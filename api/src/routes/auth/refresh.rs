@@ -0,0 +1,14 @@
+use crate::{AppState, errors::Error};
+use axum::http::HeaderMap;
+use axum::{extract::State, http::StatusCode};
+use tower_cookies::Cookies;
+
+/// Issues a new access token and rotates the refresh token. Public, since the
+/// access token is usually what expired.
+pub async fn refresh(
+    jar: Cookies,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<StatusCode, Error> {
+    state.session_client.refresh_session(&jar, &headers).await
+}
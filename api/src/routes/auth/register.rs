@@ -1,28 +1,105 @@
 use crate::AppState;
 use crate::entity::user;
-use crate::models::create_user::CreateUserRequest;
+use crate::models::create_user::{CreateUserRequest, UsernameAvailability, UsernameQuery};
 use bcrypt::{DEFAULT_COST, hash};
 
-use crate::errors::Error;
+use crate::errors::{Error, FieldError};
 use axum::{
-    extract::{Json, State},
+    extract::{Json, Query, State},
     http::StatusCode,
 };
-use sea_orm::{ActiveModelTrait, Set};
+use sea_orm::{ActiveModelTrait, Set, SqlErr};
+
+use super::validation::{
+    check_email, check_password, check_username, email_taken, email_taken_error, username_taken,
+    username_taken_error,
+};
 
 pub async fn register(
     State(state): State<AppState>,
     Json(payload): Json<CreateUserRequest>,
 ) -> Result<StatusCode, Error> {
+    let email = payload
+        .email
+        .map(|email| email.trim().to_string())
+        .filter(|email| !email.is_empty());
+
+    let errors: Vec<_> = [
+        check_username(&payload.username),
+        check_password("password", &payload.password, &payload.username),
+        email.as_deref().and_then(check_email),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+
+    let mut conflicts = Vec::new();
+    if username_taken(&state.db, &payload.username).await? {
+        conflicts.push(username_taken_error());
+    }
+    let email_in_use = match &email {
+        Some(email) => email_taken(&state.db, email).await?,
+        None => false,
+    };
+    if email_in_use {
+        conflicts.push(email_taken_error());
+    }
+    if !conflicts.is_empty() {
+        return Err(Error::Conflict(conflicts));
+    }
+
     let hashed_pw = hash(&payload.password, DEFAULT_COST)?;
 
     let new_user = user::ActiveModel {
         username: Set(payload.username),
         password: Set(hashed_pw),
+        email: Set(email),
         ..Default::default()
     };
 
-    new_user.insert(&state.db).await?;
+    // Someone may have registered the name or address since the checks
+    // above; the unique indexes have the final say.
+    match new_user.insert(&state.db).await {
+        Ok(_) => Ok(StatusCode::CREATED),
+        Err(e) => match e.sql_err() {
+            Some(SqlErr::UniqueConstraintViolation(constraint)) => {
+                Err(Error::Conflict(vec![conflict_error(&constraint)]))
+            }
+            _ => Err(e.into()),
+        },
+    }
+}
+
+/// The field behind a unique index violation, going by the index name.
+fn conflict_error(constraint: &str) -> FieldError {
+    if constraint.contains("email") {
+        email_taken_error()
+    } else {
+        username_taken_error()
+    }
+}
+
+/// Whether `username` could be registered right now, with the reason if not.
+pub async fn username_available(
+    State(state): State<AppState>,
+    Query(query): Query<UsernameQuery>,
+) -> Result<(StatusCode, Json<UsernameAvailability>), Error> {
+    let error = match check_username(&query.username) {
+        Some(error) => Some(error),
+        None => username_taken(&state.db, &query.username)
+            .await?
+            .then(username_taken_error),
+    };
 
-    Ok(StatusCode::CREATED)
+    Ok((
+        StatusCode::OK,
+        Json(UsernameAvailability {
+            available: error.is_none(),
+            username: query.username,
+            errors: error.into_iter().collect(),
+        }),
+    ))
 }
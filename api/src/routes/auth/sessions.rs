@@ -0,0 +1,19 @@
+use axum::{Extension, Json, extract::State, http::StatusCode};
+
+use crate::{
+    AppState,
+    errors::Error,
+    models::{claims::Claims, session::SessionInfo},
+};
+
+pub async fn list_sessions(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<Vec<SessionInfo>>), Error> {
+    let sessions = state
+        .session_client
+        .list_sessions(claims.sub, &claims.jti)
+        .await?;
+
+    Ok((StatusCode::OK, Json(sessions)))
+}
@@ -0,0 +1,274 @@
+use sea_orm::{
+    DatabaseConnection, EntityTrait, QueryFilter,
+    sea_query::{Expr, Func, SimpleExpr},
+};
+
+use crate::{
+    entity::user,
+    errors::{Error, FieldError},
+};
+
+const MIN_USERNAME_CHARS: usize = 3;
+const MAX_USERNAME_CHARS: usize = 32;
+const MIN_PASSWORD_CHARS: usize = 8;
+/// bcrypt ignores everything past the 72nd byte.
+const MAX_PASSWORD_BYTES: usize = 72;
+const MAX_EMAIL_CHARS: usize = 254;
+
+/// Usernames are unique regardless of case, backed by the
+/// `idx-user-username-lower` index, and looked up the same way.
+pub fn username_eq(username: &str) -> SimpleExpr {
+    Expr::expr(Func::lower(Expr::col(user::Column::Username))).eq(username.to_lowercase())
+}
+
+/// `username_eq` for any of several names.
+pub fn username_in<'a>(usernames: impl IntoIterator<Item = &'a str>) -> SimpleExpr {
+    Expr::expr(Func::lower(Expr::col(user::Column::Username)))
+        .is_in(usernames.into_iter().map(str::to_lowercase))
+}
+
+pub async fn username_taken(db: &DatabaseConnection, username: &str) -> Result<bool, Error> {
+    Ok(user::Entity::find()
+        .filter(username_eq(username))
+        .one(db)
+        .await?
+        .is_some())
+}
+
+pub fn username_taken_error() -> FieldError {
+    FieldError::new("username", "taken", "this username is already taken")
+}
+
+/// 3 to 32 characters: letters, digits, `_`, `.` and `-`, starting with a
+/// letter or digit.
+pub fn check_username(username: &str) -> Option<FieldError> {
+    let length = username.chars().count();
+    if length < MIN_USERNAME_CHARS {
+        return Some(FieldError::new(
+            "username",
+            "too_short",
+            format!("must be at least {MIN_USERNAME_CHARS} characters"),
+        ));
+    }
+    if length > MAX_USERNAME_CHARS {
+        return Some(FieldError::new(
+            "username",
+            "too_long",
+            format!("must be at most {MAX_USERNAME_CHARS} characters"),
+        ));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+    {
+        return Some(FieldError::new(
+            "username",
+            "invalid_characters",
+            "may only contain letters, digits, '_', '.' and '-'",
+        ));
+    }
+    if !username.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Some(FieldError::new(
+            "username",
+            "invalid_start",
+            "must start with a letter or digit",
+        ));
+    }
+    None
+}
+
+/// `field` names the password in the request, which differs between
+/// registration and password changes.
+pub fn check_password(field: &'static str, password: &str, username: &str) -> Option<FieldError> {
+    if password.chars().count() < MIN_PASSWORD_CHARS {
+        return Some(FieldError::new(
+            field,
+            "too_short",
+            format!("must be at least {MIN_PASSWORD_CHARS} characters"),
+        ));
+    }
+    if password.len() > MAX_PASSWORD_BYTES {
+        return Some(FieldError::new(
+            field,
+            "too_long",
+            format!("must be at most {MAX_PASSWORD_BYTES} bytes"),
+        ));
+    }
+    if password.trim().is_empty() {
+        return Some(FieldError::new(
+            field,
+            "blank",
+            "must not be only whitespace",
+        ));
+    }
+    if password.eq_ignore_ascii_case(username) {
+        return Some(FieldError::new(
+            field,
+            "matches_username",
+            "must not be the same as the username",
+        ));
+    }
+    None
+}
+
+/// Email addresses are unique regardless of case, backed by the
+/// `idx-user-email-lower` index.
+pub fn email_eq(email: &str) -> SimpleExpr {
+    Expr::expr(Func::lower(Expr::col(user::Column::Email))).eq(email.to_lowercase())
+}
+
+pub async fn email_taken(db: &DatabaseConnection, email: &str) -> Result<bool, Error> {
+    Ok(user::Entity::find()
+        .filter(email_eq(email))
+        .one(db)
+        .await?
+        .is_some())
+}
+
+pub fn email_taken_error() -> FieldError {
+    FieldError::new("email", "taken", "this email address is already in use")
+}
+
+/// Only catches obvious typos; whether the address works shows when mail is
+/// sent to it.
+pub fn check_email(email: &str) -> Option<FieldError> {
+    if email.chars().count() > MAX_EMAIL_CHARS {
+        return Some(FieldError::new(
+            "email",
+            "too_long",
+            format!("must be at most {MAX_EMAIL_CHARS} characters"),
+        ));
+    }
+    let valid = match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !domain.contains('@')
+                && !email.chars().any(|c| c.is_whitespace() || c.is_control())
+        }
+        None => false,
+    };
+    (!valid).then(|| FieldError::new("email", "invalid", "is not a valid email address"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(error: Option<FieldError>) -> Option<&'static str> {
+        error.map(|e| e.code)
+    }
+
+    #[test]
+    fn username_length_is_bounded_in_characters() {
+        assert_eq!(code(check_username("ab")), Some("too_short"));
+        assert_eq!(code(check_username("abc")), None);
+        assert_eq!(code(check_username(&"a".repeat(32))), None);
+        assert_eq!(code(check_username(&"a".repeat(33))), Some("too_long"));
+        // Three characters, six bytes: long enough, but not ASCII.
+        assert_eq!(code(check_username("ééé")), Some("invalid_characters"));
+    }
+
+    #[test]
+    fn username_allows_only_ascii_names_starting_alphanumeric() {
+        assert_eq!(code(check_username("Alice_01.x-y")), None);
+        assert_eq!(
+            code(check_username("bob smith")),
+            Some("invalid_characters")
+        );
+        assert_eq!(code(check_username("al@ce")), Some("invalid_characters"));
+        assert_eq!(code(check_username("_alice")), Some("invalid_start"));
+        assert_eq!(code(check_username(".alice")), Some("invalid_start"));
+    }
+
+    #[test]
+    fn username_lookups_fold_case() {
+        use sea_orm::sea_query::{PostgresQueryBuilder, Query};
+
+        let sql = |condition| {
+            Query::select()
+                .column(user::Column::Id)
+                .from(user::Entity)
+                .and_where(condition)
+                .to_string(PostgresQueryBuilder)
+        };
+        assert!(sql(username_eq("ÄLICE")).ends_with(r#"WHERE LOWER("username") = 'älice'"#));
+        assert!(
+            sql(username_in(["Alice", "BOB"]))
+                .ends_with(r#"WHERE LOWER("username") IN ('alice', 'bob')"#)
+        );
+    }
+
+    #[test]
+    fn password_length_is_bounded_in_characters_and_bytes() {
+        assert_eq!(
+            code(check_password("password", "1234567", "alice")),
+            Some("too_short")
+        );
+        assert_eq!(code(check_password("password", "12345678", "alice")), None);
+        assert_eq!(
+            code(check_password("password", &"a".repeat(72), "alice")),
+            None
+        );
+        assert_eq!(
+            code(check_password("password", &"a".repeat(73), "alice")),
+            Some("too_long")
+        );
+        // 37 characters, but 74 bytes once encoded.
+        assert_eq!(
+            code(check_password("password", &"ü".repeat(37), "alice")),
+            Some("too_long")
+        );
+        assert_eq!(
+            code(check_password("password", &"ü".repeat(36), "alice")),
+            None
+        );
+    }
+
+    #[test]
+    fn password_rejects_blank_and_the_username_in_any_case() {
+        assert_eq!(
+            code(check_password("password", "        ", "alice")),
+            Some("blank")
+        );
+        assert_eq!(
+            code(check_password("password", "Alice123", "alice123")),
+            Some("matches_username")
+        );
+    }
+
+    #[test]
+    fn password_errors_name_the_requested_field() {
+        let error = check_password("currentPassword", "short", "alice").unwrap();
+        assert_eq!(error.field, "currentPassword");
+    }
+
+    #[test]
+    fn email_length_is_bounded() {
+        let local = "a".repeat(254 - "@example.com".len());
+        assert_eq!(code(check_email(&format!("{local}@example.com"))), None);
+        assert_eq!(
+            code(check_email(&format!("a{local}@example.com"))),
+            Some("too_long")
+        );
+    }
+
+    #[test]
+    fn email_catches_obvious_typos() {
+        assert_eq!(code(check_email("alice@example.com")), None);
+        assert_eq!(code(check_email("Älice@exämple.com")), None);
+        for email in [
+            "alice",
+            "@example.com",
+            "alice@example",
+            "alice@.example.com",
+            "alice@example.com.",
+            "alice@ex@ample.com",
+            "alice smith@example.com",
+        ] {
+            assert_eq!(code(check_email(email)), Some("invalid"), "{email}");
+        }
+    }
+}
@@ -0,0 +1,3 @@
+mod serve;
+
+pub use serve::get_blob;
@@ -0,0 +1,36 @@
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+};
+
+use crate::{AppState, clients::is_valid_key, errors::Error};
+
+/// Serves uploads kept by the local blob store.
+pub async fn get_blob(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<impl IntoResponse, Error> {
+    if !is_valid_key(&key) {
+        return Err(Error::NotFound);
+    }
+    let bytes = state.blob_store.get(&key).await?.ok_or(Error::NotFound)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type(&key)),
+            (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+        ],
+        bytes,
+    ))
+}
+
+fn content_type(key: &str) -> &'static str {
+    match key.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("png") => "image/png",
+        Some("jpg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
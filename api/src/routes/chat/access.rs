@@ -0,0 +1,169 @@
+use chrono::Utc;
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter,
+    sea_query::Query,
+};
+
+use crate::{
+    entity::{
+        chat,
+        chat_member::{self, MemberRole},
+        chat_sanction::{self, SanctionKind},
+        direct_chat,
+    },
+    errors::Error,
+};
+
+/// Loads a chat the user is allowed to see: `NotFound` when it does not exist,
+/// `Forbidden` when it is a direct conversation between two other users, a
+/// private room the user has not joined, or a room the user is banned from.
+pub async fn find_accessible_chat(
+    db: &DatabaseConnection,
+    chat_id: i32,
+    user_id: i32,
+) -> Result<chat::Model, Error> {
+    let chat_row = chat::Entity::find_by_id(chat_id)
+        .one(db)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    if chat_row.is_direct && !is_direct_participant(db, chat_id, user_id).await? {
+        return Err(Error::Forbidden);
+    }
+
+    if chat_row.is_private && !is_member(db, chat_id, user_id).await? {
+        return Err(Error::Forbidden);
+    }
+
+    if has_active_sanction(db, chat_id, user_id, SanctionKind::Ban).await? {
+        return Err(Error::Forbidden);
+    }
+
+    Ok(chat_row)
+}
+
+/// Rooms that show up in listings for `user_id`: every public room plus the
+/// private rooms they have joined. Direct conversations are never listed.
+pub fn listed_for(user_id: i32) -> Condition {
+    Condition::all().add(chat::Column::IsDirect.eq(false)).add(
+        Condition::any().add(chat::Column::IsPrivate.eq(false)).add(
+            chat::Column::Id.in_subquery(
+                Query::select()
+                    .column(chat_member::Column::ChatId)
+                    .from(chat_member::Entity)
+                    .and_where(chat_member::Column::UserId.eq(user_id))
+                    .and_where(chat_member::Column::JoinedAt.is_not_null())
+                    .to_owned(),
+            ),
+        ),
+    )
+}
+
+/// Rooms whose messages `user_id` may read: everything `listed_for` returns,
+/// plus their direct conversations, minus rooms they are currently banned from.
+pub fn readable_by(user_id: i32) -> Condition {
+    Condition::all()
+        .add(
+            Condition::any().add(listed_for(user_id)).add(
+                chat::Column::Id.in_subquery(
+                    Query::select()
+                        .column(direct_chat::Column::ChatId)
+                        .from(direct_chat::Entity)
+                        .cond_where(
+                            Condition::any()
+                                .add(direct_chat::Column::UserLowId.eq(user_id))
+                                .add(direct_chat::Column::UserHighId.eq(user_id)),
+                        )
+                        .to_owned(),
+                ),
+            ),
+        )
+        .add(
+            chat::Column::Id.not_in_subquery(
+                Query::select()
+                    .column(chat_sanction::Column::ChatId)
+                    .from(chat_sanction::Entity)
+                    .and_where(chat_sanction::Column::UserId.eq(user_id))
+                    .and_where(chat_sanction::Column::Kind.eq(SanctionKind::Ban))
+                    .cond_where(
+                        Condition::any()
+                            .add(chat_sanction::Column::ExpiresAt.is_null())
+                            .add(chat_sanction::Column::ExpiresAt.gt(Utc::now().naive_utc())),
+                    )
+                    .to_owned(),
+            ),
+        )
+}
+
+/// Whether the user has accepted membership of the room. Pending invitations
+/// do not count.
+pub async fn is_member(db: &DatabaseConnection, chat_id: i32, user_id: i32) -> Result<bool, Error> {
+    let count = chat_member::Entity::find()
+        .filter(chat_member::Column::ChatId.eq(chat_id))
+        .filter(chat_member::Column::UserId.eq(user_id))
+        .filter(chat_member::Column::JoinedAt.is_not_null())
+        .count(db)
+        .await?;
+
+    Ok(count > 0)
+}
+
+async fn is_direct_participant(
+    db: &DatabaseConnection,
+    chat_id: i32,
+    user_id: i32,
+) -> Result<bool, Error> {
+    let count = direct_chat::Entity::find_by_id(chat_id)
+        .filter(
+            direct_chat::Column::UserLowId
+                .eq(user_id)
+                .or(direct_chat::Column::UserHighId.eq(user_id)),
+        )
+        .count(db)
+        .await?;
+
+    Ok(count > 0)
+}
+
+/// The user's role in the room. The chat owner is always `Owner`; anyone
+/// without a stored role is a plain `Member`.
+pub async fn member_role(
+    db: &DatabaseConnection,
+    chat_row: &chat::Model,
+    user_id: i32,
+) -> Result<MemberRole, Error> {
+    if chat_row.owner_id == user_id {
+        return Ok(MemberRole::Owner);
+    }
+
+    let membership = chat_member::Entity::find()
+        .filter(chat_member::Column::ChatId.eq(chat_row.id))
+        .filter(chat_member::Column::UserId.eq(user_id))
+        .one(db)
+        .await?;
+
+    Ok(membership.map_or(MemberRole::Member, |m| m.role))
+}
+
+/// Whether a ban or mute is currently in force. Sanctions without an expiry
+/// are permanent.
+pub async fn has_active_sanction(
+    db: &DatabaseConnection,
+    chat_id: i32,
+    user_id: i32,
+    kind: SanctionKind,
+) -> Result<bool, Error> {
+    let count = chat_sanction::Entity::find()
+        .filter(chat_sanction::Column::ChatId.eq(chat_id))
+        .filter(chat_sanction::Column::UserId.eq(user_id))
+        .filter(chat_sanction::Column::Kind.eq(kind))
+        .filter(
+            Condition::any()
+                .add(chat_sanction::Column::ExpiresAt.is_null())
+                .add(chat_sanction::Column::ExpiresAt.gt(Utc::now().naive_utc())),
+        )
+        .count(db)
+        .await?;
+
+    Ok(count > 0)
+}
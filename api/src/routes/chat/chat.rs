@@ -1,54 +1,104 @@
 use axum::{
-    Json,
+    Extension, Json,
     extract::{Path, State},
     http::StatusCode,
 };
+use chrono::Utc;
 
 use crate::{
     AppState,
-    entity::{chat, online_user},
+    entity::{chat, chat_member},
     errors::Error,
-    models::chat::{Chat, CreateChatRequest, GetChatResponse, PreviousMessage},
+    models::{
+        chat::{Chat, CreateChatRequest, GetChatResponse},
+        claims::Claims,
+        messages::MessagePayload,
+    },
 };
 
-use migration::SimpleExpr;
+use super::{
+    access::{find_accessible_chat, listed_for},
+    presence::attach_active_users,
+    reactions::attach_reactions,
+};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, EntityTrait, QueryFilter, QuerySelect, sea_query::Expr,
+    ActiveModelTrait, ActiveValue::Set, EntityTrait, QueryFilter, QuerySelect, Select,
+    TransactionTrait, sea_query::Expr,
 };
 
-pub async fn active_chats(State(state): State<AppState>) -> Result<Json<Vec<Chat>>, Error> {
-    let rows = chat::Entity::find()
-        .left_join(online_user::Entity)
-        .select_only()
-        .column(chat::Column::Id)
-        .column(chat::Column::Name)
-        .column_as(
-            Expr::col((online_user::Entity, online_user::Column::UserId)).count(),
-            "active_users",
-        )
-        .group_by(chat::Column::Id)
+pub async fn active_chats(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Chat>>, Error> {
+    let mut rows = chat_summaries(claims.sub)
         .into_model::<Chat>()
         .all(&state.db)
         .await?;
+    attach_active_users(&state, &mut rows).await?;
 
     Ok(Json(rows))
 }
 
+/// Rooms listed for `user_id`, shaped as `Chat` rows with their unread
+/// counts. `active_users` comes from presence via `attach_active_users`.
+pub(super) fn chat_summaries(user_id: i32) -> Select<chat::Entity> {
+    chat::Entity::find()
+        .filter(listed_for(user_id))
+        .select_only()
+        .column(chat::Column::Id)
+        .column(chat::Column::Name)
+        .column_as(
+            Expr::cust_with_values(
+                "(SELECT COUNT(*) FROM message AS unread \
+                 WHERE unread.chat_id = chat.id \
+                 AND unread.sender_id <> $1 \
+                 AND unread.deleted_at IS NULL \
+                 AND unread.id > COALESCE((SELECT read_receipt.last_read_message_id \
+                 FROM read_receipt WHERE read_receipt.chat_id = chat.id \
+                 AND read_receipt.user_id = $1), 0))",
+                [user_id],
+            ),
+            "unread_count",
+        )
+}
+
 pub async fn create_chat(
+    Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
     Json(payload): Json<CreateChatRequest>,
 ) -> Result<(StatusCode, Json<chat::Model>), Error> {
+    let txn = state.db.begin().await?;
+
     let new_chat = chat::ActiveModel {
         name: Set(payload.name),
-        owner_id: Set(payload.owner_id),
+        owner_id: Set(claims.sub),
+        is_private: Set(payload.is_private),
         ..Default::default()
     };
 
-    let inserted: chat::Model = new_chat.insert(&state.db).await?;
+    let inserted: chat::Model = new_chat.insert(&txn).await?;
+
+    if inserted.is_private {
+        chat_member::ActiveModel {
+            chat_id: Set(inserted.id),
+            user_id: Set(inserted.owner_id),
+            joined_at: Set(Some(Utc::now().naive_utc())),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+    }
+
+    txn.commit().await?;
+
+    // Private rooms are only announced to their members.
+    if inserted.is_private {
+        return Ok((StatusCode::CREATED, Json(inserted)));
+    }
 
     let payload = serde_json::json!({
         "type": "new_chat",
-        "content": {"id": inserted.id, "name": inserted.name, "active_users": 0},
+        "content": {"id": inserted.id, "name": inserted.name, "active_users": 0, "unread_count": 0},
     })
     .to_string();
 
@@ -57,36 +107,23 @@ pub async fn create_chat(
     Ok((StatusCode::CREATED, Json(inserted)))
 }
 
-pub async fn get_all_chats_by_name(
-    State(state): State<AppState>,
-    Path(name): Path<String>,
-) -> Result<(StatusCode, Json<Vec<chat::Model>>), Error> {
-    let chats = chat::Entity::find()
-        .filter(SimpleExpr::Custom(format!("name ILIKE '{name}%'")))
-        .all(&state.db)
-        .await?;
-
-    Ok((StatusCode::OK, Json(chats)))
-}
-
 pub async fn get_chat(
+    Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
     Path(id): Path<i32>,
 ) -> Result<(StatusCode, Json<GetChatResponse>), Error> {
-    let chat_row = chat::Entity::find_by_id(id)
-        .one(&state.db)
-        .await?
-        .ok_or(Error::NotFound)?;
+    let chat_row = find_accessible_chat(&state.db, id, claims.sub).await?;
 
     let raw_messages: Vec<String> = state
         .redis_client
         .lrange(&format!("chat_messages:{id}"), 0, 9)
         .await?;
-    let mut messages: Vec<PreviousMessage> = raw_messages
+    let mut messages: Vec<MessagePayload> = raw_messages
         .into_iter()
-        .filter_map(|s| serde_json::from_str::<PreviousMessage>(&s).ok())
+        .filter_map(|s| serde_json::from_str::<MessagePayload>(&s).ok())
         .collect();
     messages.reverse();
+    attach_reactions(&state.db, &mut messages, claims.sub).await?;
 
     let resp = GetChatResponse {
         id: chat_row.id,
@@ -0,0 +1,143 @@
+use axum::{Extension, Json, extract::State, http::StatusCode};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, TransactionTrait,
+};
+
+use crate::{
+    AppState,
+    entity::{chat, direct_chat, user},
+    errors::Error,
+    models::{
+        chat::{DirectChat, OpenDirectChatRequest},
+        claims::Claims,
+    },
+};
+
+pub async fn open_direct_chat(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<OpenDirectChatRequest>,
+) -> Result<(StatusCode, Json<DirectChat>), Error> {
+    if payload.user_id == claims.sub {
+        return Err(Error::BadRequest("cannot open a direct chat with yourself"));
+    }
+
+    let other = user::Entity::find_by_id(payload.user_id)
+        .one(&state.db)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    let (low, high) = if claims.sub < other.id {
+        (claims.sub, other.id)
+    } else {
+        (other.id, claims.sub)
+    };
+
+    let response = |chat_id: i32| DirectChat {
+        id: chat_id,
+        other_user_id: other.id,
+        other_username: other.username.clone(),
+    };
+
+    if let Some(existing) = find_direct_chat(&state.db, low, high).await? {
+        return Ok((StatusCode::OK, Json(response(existing.chat_id))));
+    }
+
+    match create_direct_chat(&state.db, &claims, &other, low, high).await {
+        Ok(chat_id) => Ok((StatusCode::CREATED, Json(response(chat_id)))),
+        // Lost a race against the other participant opening the same chat.
+        Err(e) => match find_direct_chat(&state.db, low, high).await? {
+            Some(existing) => Ok((StatusCode::OK, Json(response(existing.chat_id)))),
+            None => Err(e),
+        },
+    }
+}
+
+pub async fn list_direct_chats(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<DirectChat>>, Error> {
+    let rows = direct_chat::Entity::find()
+        .filter(
+            direct_chat::Column::UserLowId
+                .eq(claims.sub)
+                .or(direct_chat::Column::UserHighId.eq(claims.sub)),
+        )
+        .order_by_desc(direct_chat::Column::ChatId)
+        .all(&state.db)
+        .await?;
+
+    let other_ids: Vec<i32> = rows
+        .iter()
+        .map(|row| other_participant(row, claims.sub))
+        .collect();
+    let users = user::Entity::find()
+        .filter(user::Column::Id.is_in(other_ids))
+        .all(&state.db)
+        .await?;
+
+    let chats = rows
+        .iter()
+        .filter_map(|row| {
+            let other_id = other_participant(row, claims.sub);
+            users.iter().find(|u| u.id == other_id).map(|u| DirectChat {
+                id: row.chat_id,
+                other_user_id: u.id,
+                other_username: u.username.clone(),
+            })
+        })
+        .collect();
+
+    Ok(Json(chats))
+}
+
+fn other_participant(row: &direct_chat::Model, user_id: i32) -> i32 {
+    if row.user_low_id == user_id {
+        row.user_high_id
+    } else {
+        row.user_low_id
+    }
+}
+
+async fn find_direct_chat(
+    db: &DatabaseConnection,
+    low: i32,
+    high: i32,
+) -> Result<Option<direct_chat::Model>, Error> {
+    Ok(direct_chat::Entity::find()
+        .filter(direct_chat::Column::UserLowId.eq(low))
+        .filter(direct_chat::Column::UserHighId.eq(high))
+        .one(db)
+        .await?)
+}
+
+async fn create_direct_chat(
+    db: &DatabaseConnection,
+    claims: &Claims,
+    other: &user::Model,
+    low: i32,
+    high: i32,
+) -> Result<i32, Error> {
+    let txn = db.begin().await?;
+
+    let inserted = chat::ActiveModel {
+        name: Set(format!("{} & {}", claims.username, other.username)),
+        owner_id: Set(claims.sub),
+        is_direct: Set(true),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+
+    direct_chat::ActiveModel {
+        chat_id: Set(inserted.id),
+        user_low_id: Set(low),
+        user_high_id: Set(high),
+    }
+    .insert(&txn)
+    .await?;
+
+    txn.commit().await?;
+    Ok(inserted.id)
+}
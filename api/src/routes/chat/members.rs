@@ -0,0 +1,180 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+
+use crate::{
+    AppState,
+    entity::{
+        chat,
+        chat_member::{self, MemberRole},
+        user,
+    },
+    errors::Error,
+    models::{
+        chat::{ChatMember, Invitation, InviteMemberRequest},
+        claims::Claims,
+        messages::OutgoingMessage,
+    },
+    routes::notifications::publish_to_user,
+};
+
+use super::access::find_accessible_chat;
+
+pub async fn list_members(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<ChatMember>>, Error> {
+    let chat_row = find_accessible_chat(&state.db, id, claims.sub).await?;
+
+    let mut members = chat_member::Entity::find()
+        .filter(chat_member::Column::ChatId.eq(id))
+        .inner_join(user::Entity)
+        .select_only()
+        .column(chat_member::Column::UserId)
+        .column_as(user::Column::Username, "username")
+        .column(chat_member::Column::InvitedBy)
+        .column(chat_member::Column::JoinedAt)
+        .column(chat_member::Column::Role)
+        .order_by_asc(chat_member::Column::Id)
+        .into_model::<ChatMember>()
+        .all(&state.db)
+        .await?;
+
+    for member in members.iter_mut() {
+        if member.user_id == chat_row.owner_id {
+            member.role = MemberRole::Owner;
+        }
+    }
+
+    Ok(Json(members))
+}
+
+pub async fn invite_member(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<InviteMemberRequest>,
+) -> Result<StatusCode, Error> {
+    let chat_row = find_accessible_chat(&state.db, id, claims.sub).await?;
+    if !chat_row.is_private || chat_row.is_direct {
+        return Err(Error::BadRequest("only private rooms take invitations"));
+    }
+
+    user::Entity::find_by_id(payload.user_id)
+        .one(&state.db)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    let existing = chat_member::Entity::find()
+        .filter(chat_member::Column::ChatId.eq(id))
+        .filter(chat_member::Column::UserId.eq(payload.user_id))
+        .one(&state.db)
+        .await?;
+    if existing.is_some() {
+        return Ok(StatusCode::OK);
+    }
+
+    let invited = chat_member::ActiveModel {
+        chat_id: Set(id),
+        user_id: Set(payload.user_id),
+        invited_by: Set(Some(claims.sub)),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await?;
+
+    let invitation = Invitation {
+        chat_id: id,
+        chat_name: chat_row.name,
+        invited_by: invited.invited_by,
+        created_at: invited.created_at,
+    };
+    publish_to_user(
+        &state,
+        payload.user_id,
+        &OutgoingMessage::Invitation(invitation),
+    )
+    .await;
+
+    Ok(StatusCode::CREATED)
+}
+
+pub async fn accept_invitation(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<StatusCode, Error> {
+    let membership = chat_member::Entity::find()
+        .filter(chat_member::Column::ChatId.eq(id))
+        .filter(chat_member::Column::UserId.eq(claims.sub))
+        .one(&state.db)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    if membership.joined_at.is_some() {
+        return Ok(StatusCode::OK);
+    }
+
+    let mut active: chat_member::ActiveModel = membership.into();
+    active.joined_at = Set(Some(Utc::now().naive_utc()));
+    active.update(&state.db).await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Leaves a room, or declines a pending invitation to it.
+pub async fn leave_chat(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<StatusCode, Error> {
+    let chat_row = chat::Entity::find_by_id(id)
+        .one(&state.db)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    if chat_row.is_private && chat_row.owner_id == claims.sub {
+        return Err(Error::BadRequest("the owner cannot leave the room"));
+    }
+
+    let deleted = chat_member::Entity::delete_many()
+        .filter(chat_member::Column::ChatId.eq(id))
+        .filter(chat_member::Column::UserId.eq(claims.sub))
+        .exec(&state.db)
+        .await?;
+
+    if deleted.rows_affected == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn list_invitations(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Invitation>>, Error> {
+    let invitations = chat_member::Entity::find()
+        .filter(chat_member::Column::UserId.eq(claims.sub))
+        .filter(chat_member::Column::JoinedAt.is_null())
+        .inner_join(chat::Entity)
+        .select_only()
+        .column(chat_member::Column::ChatId)
+        .column_as(chat::Column::Name, "chat_name")
+        .column(chat_member::Column::InvitedBy)
+        .column(chat_member::Column::CreatedAt)
+        .order_by_desc(chat_member::Column::CreatedAt)
+        .into_model::<Invitation>()
+        .all(&state.db)
+        .await?;
+
+    Ok(Json(invitations))
+}
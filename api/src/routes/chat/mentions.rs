@@ -0,0 +1,161 @@
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter};
+use tracing::error;
+
+use crate::{
+    AppState,
+    entity::{
+        notification::{self, NotificationKind},
+        user,
+    },
+    models::messages::{MessagePayload, OutgoingMessage},
+    routes::{
+        auth::username_in,
+        notifications::{load_notification, publish_to_user},
+    },
+};
+
+use super::access::find_accessible_chat;
+
+const MAX_MENTIONS: usize = 10;
+
+/// Extracts the distinct `@username` handles from a message, in order of
+/// appearance. Handles that only differ in case name the same user and are
+/// kept once. An `@` preceded by a word character (as in an email address)
+/// does not start a mention.
+fn parse_mentions(content: &str) -> Vec<&str> {
+    let is_handle_char = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '.');
+    let mut mentions = Vec::new();
+    let mut prev: Option<char> = None;
+
+    for (i, c) in content.char_indices() {
+        let starts_mention = c == '@' && !prev.is_some_and(|p| p.is_alphanumeric() || p == '_');
+        prev = Some(c);
+        if !starts_mention {
+            continue;
+        }
+
+        let rest = &content[i + 1..];
+        let end = rest
+            .find(|c: char| !is_handle_char(c))
+            .unwrap_or(rest.len());
+        // Trailing punctuation belongs to the sentence, not the handle.
+        let handle = rest[..end].trim_end_matches(['.', '-']);
+        let is_new = !mentions
+            .iter()
+            .any(|m: &&str| m.to_lowercase() == handle.to_lowercase());
+        if !handle.is_empty() && is_new {
+            mentions.push(handle);
+            if mentions.len() == MAX_MENTIONS {
+                break;
+            }
+        }
+    }
+
+    mentions
+}
+
+/// Stores a notification for every user mentioned in the message who can see
+/// the room, and pushes a `mention` event on their user channel. Senders do
+/// not get notified about mentioning themselves.
+pub async fn notify_mentions(state: &AppState, message: &MessagePayload) {
+    let handles = parse_mentions(&message.content);
+    if handles.is_empty() {
+        return;
+    }
+
+    let mentioned = match user::Entity::find()
+        .filter(username_in(handles))
+        .filter(user::Column::Id.ne(message.sender_id))
+        .all(&state.db)
+        .await
+    {
+        Ok(users) => users,
+        Err(e) => {
+            error!("failed to resolve mentions: {e:?}");
+            return;
+        }
+    };
+
+    for target in mentioned {
+        if find_accessible_chat(&state.db, message.chat_id, target.id)
+            .await
+            .is_err()
+        {
+            continue;
+        }
+
+        let inserted = notification::ActiveModel {
+            user_id: Set(target.id),
+            kind: Set(NotificationKind::Mention),
+            chat_id: Set(message.chat_id),
+            message_id: Set(message.id),
+            actor_id: Set(Some(message.sender_id)),
+            ..Default::default()
+        }
+        .insert(&state.db)
+        .await;
+
+        let notification = match inserted {
+            Ok(row) => load_notification(&state.db, row.id).await,
+            Err(e) => Err(e),
+        };
+        match notification {
+            Ok(Some(payload)) => {
+                publish_to_user(state, target.id, &OutgoingMessage::Mention(payload)).await;
+            }
+            Ok(None) => {}
+            Err(e) => error!("failed to store mention notification: {e:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_handles_in_order_of_appearance() {
+        assert_eq!(
+            parse_mentions("@bob and @alice, ask @carol_1"),
+            ["bob", "alice", "carol_1"]
+        );
+        assert!(parse_mentions("no mentions here @").is_empty());
+    }
+
+    #[test]
+    fn ignores_at_signs_inside_words() {
+        assert!(parse_mentions("mail alice@example.com").is_empty());
+        assert!(parse_mentions("x_@bob").is_empty());
+        assert_eq!(parse_mentions("(@bob)"), ["bob"]);
+    }
+
+    #[test]
+    fn strips_trailing_sentence_punctuation() {
+        assert_eq!(parse_mentions("thanks @bob."), ["bob"]);
+        assert_eq!(parse_mentions("@bob.smith- said"), ["bob.smith"]);
+    }
+
+    #[test]
+    fn keeps_handles_differing_only_in_case_once() {
+        assert_eq!(parse_mentions("@Bob @bob @BOB"), ["Bob"]);
+        assert_eq!(parse_mentions("@Émile @émile"), ["Émile"]);
+    }
+
+    #[test]
+    fn stops_at_the_mention_limit() {
+        let content = (0..15)
+            .map(|i| format!("@user{i}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mentions = parse_mentions(&content);
+        assert_eq!(mentions.len(), MAX_MENTIONS);
+        assert_eq!(mentions.last(), Some(&"user9"));
+    }
+
+    #[test]
+    fn duplicates_do_not_count_towards_the_limit() {
+        let mut content = "@bob ".repeat(20);
+        content.push_str("@alice");
+        assert_eq!(parse_mentions(&content), ["bob", "alice"]);
+    }
+}
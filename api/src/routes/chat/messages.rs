@@ -0,0 +1,136 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use sea_orm::{
+    ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Select, sea_query::Expr,
+};
+
+use crate::{
+    AppState,
+    entity::{message, user},
+    errors::Error,
+    models::{
+        chat::{MessageHistoryQuery, MessageHistoryResponse, ThreadResponse},
+        claims::Claims,
+        messages::MessagePayload,
+    },
+};
+
+use super::{access::find_accessible_chat, reactions::attach_reactions};
+
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 100;
+
+/// Selects messages joined with their sender, shaped as `MessagePayload` rows.
+fn message_payloads() -> Select<message::Entity> {
+    message::Entity::find()
+        .inner_join(user::Entity)
+        .select_only()
+        .column(message::Column::Id)
+        .column(message::Column::ChatId)
+        .column(message::Column::SenderId)
+        .column_as(user::Column::Username, "username")
+        .column_as(user::Column::DisplayName, "display_name")
+        .column_as(user::Column::AvatarUrl, "avatar_url")
+        .column(message::Column::Content)
+        .column(message::Column::CreatedAt)
+        .column(message::Column::EditedAt)
+        .column(message::Column::DeletedAt)
+        .column(message::Column::ParentId)
+        .column_as(
+            Expr::cust(
+                "(SELECT COUNT(*) FROM message AS reply \
+                 WHERE reply.parent_id = message.id AND reply.deleted_at IS NULL)",
+            ),
+            "reply_count",
+        )
+}
+
+fn redact_deleted(messages: &mut [MessagePayload]) {
+    for m in messages.iter_mut().filter(|m| m.deleted_at.is_some()) {
+        m.content.clear();
+    }
+}
+
+pub async fn get_messages(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Query(params): Query<MessageHistoryQuery>,
+) -> Result<(StatusCode, Json<MessageHistoryResponse>), Error> {
+    find_accessible_chat(&state.db, id, claims.sub).await?;
+
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let mut query = message_payloads()
+        .filter(message::Column::ChatId.eq(id))
+        .filter(message::Column::ParentId.is_null());
+    if let Some(before) = params.before {
+        query = query.filter(message::Column::Id.lt(before));
+    }
+
+    // Fetch one extra row so we know whether an older page exists.
+    let mut messages = query
+        .order_by_desc(message::Column::Id)
+        .limit(limit + 1)
+        .into_model::<MessagePayload>()
+        .all(&state.db)
+        .await?;
+
+    let has_more = messages.len() as u64 > limit;
+    messages.truncate(limit as usize);
+    messages.reverse();
+
+    redact_deleted(&mut messages);
+    attach_reactions(&state.db, &mut messages, claims.sub).await?;
+
+    let next_cursor = if has_more {
+        messages.first().map(|m| m.id)
+    } else {
+        None
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(MessageHistoryResponse {
+            messages,
+            next_cursor,
+        }),
+    ))
+}
+
+pub async fn get_thread(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path((id, message_id)): Path<(i32, i32)>,
+) -> Result<(StatusCode, Json<ThreadResponse>), Error> {
+    find_accessible_chat(&state.db, id, claims.sub).await?;
+
+    let mut parent = message_payloads()
+        .filter(message::Column::Id.eq(message_id))
+        .filter(message::Column::ChatId.eq(id))
+        .filter(message::Column::ParentId.is_null())
+        .into_model::<MessagePayload>()
+        .one(&state.db)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    let mut replies = message_payloads()
+        .filter(message::Column::ParentId.eq(message_id))
+        .order_by_asc(message::Column::Id)
+        .into_model::<MessagePayload>()
+        .all(&state.db)
+        .await?;
+
+    redact_deleted(std::slice::from_mut(&mut parent));
+    redact_deleted(&mut replies);
+    attach_reactions(&state.db, std::slice::from_mut(&mut parent), claims.sub).await?;
+    attach_reactions(&state.db, &mut replies, claims.sub).await?;
+
+    Ok((StatusCode::OK, Json(ThreadResponse { parent, replies })))
+}
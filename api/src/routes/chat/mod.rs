@@ -1,8 +1,31 @@
+mod access;
 #[allow(clippy::module_inception)]
 mod chat;
+mod direct;
+mod members;
+mod mentions;
+mod messages;
+mod moderation;
+mod presence;
+mod reactions;
+mod search;
+mod user_events;
 mod ws_chat;
 mod ws_chat_list;
+mod ws_session;
 
-pub use chat::{active_chats, create_chat, get_all_chats_by_name, get_chat};
-pub use ws_chat::chat_ws;
+pub use chat::{active_chats, create_chat, get_chat};
+pub use direct::{list_direct_chats, open_direct_chat};
+pub use members::{accept_invitation, invite_member, leave_chat, list_invitations, list_members};
+pub use messages::{get_messages, get_thread};
+pub use moderation::{
+    ban_member, kick_member, moderation_log, mute_member, set_member_role, unban_member,
+    unmute_member,
+};
+pub use presence::{
+    HEARTBEAT_INTERVAL, broadcast_user_status, clear_user_socket, is_online, mark_user_socket,
+    next_connection_id, refresh_user_lists, spawn_presence_reaper,
+};
+pub use search::{search_chats, search_messages};
+pub use ws_chat::{chat_ws, rooms_ws};
 pub use ws_chat_list::chat_list_ws;
@@ -0,0 +1,462 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, TransactionTrait, sea_query::OnConflict,
+};
+
+use crate::{
+    AppState,
+    entity::{
+        chat,
+        chat_member::{self, MemberRole},
+        chat_sanction::{self, SanctionKind},
+        moderation_log::{self, ModerationAction},
+        user,
+    },
+    errors::{Error, FieldError},
+    models::{
+        chat::{ModerationRequest, SetRoleRequest},
+        claims::Claims,
+        messages::OutgoingMessage,
+    },
+    routes::notifications::publish_to_user,
+};
+
+use super::{
+    access::{find_accessible_chat, member_role},
+    ws_chat::publish_event,
+};
+
+const MODERATION_LOG_LIMIT: u64 = 100;
+
+pub async fn kick_member(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<ModerationRequest>,
+) -> Result<StatusCode, Error> {
+    let chat_row = authorize(&state, id, claims.sub, payload.user_id).await?;
+
+    let txn = state.db.begin().await?;
+    // Without their membership the user cannot simply reconnect to a private
+    // room; public rooms stay open to them.
+    if chat_row.is_private {
+        chat_member::Entity::delete_many()
+            .filter(chat_member::Column::ChatId.eq(id))
+            .filter(chat_member::Column::UserId.eq(payload.user_id))
+            .exec(&txn)
+            .await?;
+    }
+    record_action(
+        &txn,
+        id,
+        claims.sub,
+        payload.user_id,
+        ModerationAction::Kick,
+        payload.reason.clone(),
+        None,
+    )
+    .await?;
+    txn.commit().await?;
+    publish_removed(
+        &state,
+        id,
+        payload.user_id,
+        ModerationAction::Kick,
+        payload.reason,
+    )
+    .await;
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn ban_member(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<ModerationRequest>,
+) -> Result<StatusCode, Error> {
+    authorize(&state, id, claims.sub, payload.user_id).await?;
+
+    let expires_at = expiry(payload.duration_secs)?;
+    let txn = state.db.begin().await?;
+    apply_sanction(
+        &txn,
+        id,
+        claims.sub,
+        payload.user_id,
+        SanctionKind::Ban,
+        expires_at,
+    )
+    .await?;
+    record_action(
+        &txn,
+        id,
+        claims.sub,
+        payload.user_id,
+        ModerationAction::Ban,
+        payload.reason.clone(),
+        expires_at,
+    )
+    .await?;
+    txn.commit().await?;
+    publish_removed(
+        &state,
+        id,
+        payload.user_id,
+        ModerationAction::Ban,
+        payload.reason,
+    )
+    .await;
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn unban_member(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<ModerationRequest>,
+) -> Result<StatusCode, Error> {
+    authorize(&state, id, claims.sub, payload.user_id).await?;
+
+    let txn = state.db.begin().await?;
+    lift_sanction(&txn, id, payload.user_id, SanctionKind::Ban).await?;
+    record_action(
+        &txn,
+        id,
+        claims.sub,
+        payload.user_id,
+        ModerationAction::Unban,
+        payload.reason,
+        None,
+    )
+    .await?;
+    txn.commit().await?;
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn mute_member(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<ModerationRequest>,
+) -> Result<StatusCode, Error> {
+    authorize(&state, id, claims.sub, payload.user_id).await?;
+
+    let expires_at = expiry(payload.duration_secs)?;
+    let txn = state.db.begin().await?;
+    apply_sanction(
+        &txn,
+        id,
+        claims.sub,
+        payload.user_id,
+        SanctionKind::Mute,
+        expires_at,
+    )
+    .await?;
+    record_action(
+        &txn,
+        id,
+        claims.sub,
+        payload.user_id,
+        ModerationAction::Mute,
+        payload.reason,
+        expires_at,
+    )
+    .await?;
+    txn.commit().await?;
+    publish_mute_changed(&state, id, payload.user_id, true, expires_at).await;
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn unmute_member(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<ModerationRequest>,
+) -> Result<StatusCode, Error> {
+    authorize(&state, id, claims.sub, payload.user_id).await?;
+
+    let txn = state.db.begin().await?;
+    lift_sanction(&txn, id, payload.user_id, SanctionKind::Mute).await?;
+    record_action(
+        &txn,
+        id,
+        claims.sub,
+        payload.user_id,
+        ModerationAction::Unmute,
+        payload.reason,
+        None,
+    )
+    .await?;
+    txn.commit().await?;
+    publish_mute_changed(&state, id, payload.user_id, false, None).await;
+
+    Ok(StatusCode::OK)
+}
+
+/// Promotes a member to moderator or demotes them back. Only the owner may
+/// change roles, and in private rooms only of people who have joined.
+pub async fn set_member_role(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path((id, user_id)): Path<(i32, i32)>,
+    Json(payload): Json<SetRoleRequest>,
+) -> Result<StatusCode, Error> {
+    let chat_row = find_moderated_chat(&state, id, claims.sub).await?;
+    if chat_row.owner_id != claims.sub {
+        return Err(Error::Forbidden);
+    }
+    if payload.role == MemberRole::Owner || user_id == chat_row.owner_id {
+        return Err(Error::BadRequest("ownership cannot be changed"));
+    }
+
+    user::Entity::find_by_id(user_id)
+        .one(&state.db)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    let txn = state.db.begin().await?;
+    if chat_row.is_private {
+        // Only people who have joined can be given a role; promoting someone
+        // must not let them into the room.
+        let membership = chat_member::Entity::find()
+            .filter(chat_member::Column::ChatId.eq(id))
+            .filter(chat_member::Column::UserId.eq(user_id))
+            .one(&txn)
+            .await?
+            .ok_or(Error::NotFound)?;
+        if membership.joined_at.is_none() {
+            return Err(Error::Conflict(vec![FieldError::new(
+                "userId",
+                "not_joined",
+                "has not accepted their invitation yet",
+            )]));
+        }
+
+        let mut membership: chat_member::ActiveModel = membership.into();
+        membership.role = Set(payload.role);
+        membership.update(&txn).await?;
+    } else {
+        // Public rooms have no membership rows until someone is given a role.
+        let membership = chat_member::ActiveModel {
+            chat_id: Set(id),
+            user_id: Set(user_id),
+            role: Set(payload.role),
+            joined_at: Set(Some(Utc::now().naive_utc())),
+            ..Default::default()
+        };
+        chat_member::Entity::insert(membership)
+            .on_conflict(
+                OnConflict::columns([chat_member::Column::ChatId, chat_member::Column::UserId])
+                    .update_column(chat_member::Column::Role)
+                    .to_owned(),
+            )
+            .exec_without_returning(&txn)
+            .await?;
+    }
+
+    record_action(
+        &txn,
+        id,
+        claims.sub,
+        user_id,
+        ModerationAction::SetRole,
+        serde_json::to_value(payload.role)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string)),
+        None,
+    )
+    .await?;
+    txn.commit().await?;
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn moderation_log(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<moderation_log::Model>>, Error> {
+    let chat_row = find_moderated_chat(&state, id, claims.sub).await?;
+    if member_role(&state.db, &chat_row, claims.sub).await? < MemberRole::Moderator {
+        return Err(Error::Forbidden);
+    }
+
+    let entries = moderation_log::Entity::find()
+        .filter(moderation_log::Column::ChatId.eq(id))
+        .order_by_desc(moderation_log::Column::Id)
+        .limit(MODERATION_LOG_LIMIT)
+        .all(&state.db)
+        .await?;
+
+    Ok(Json(entries))
+}
+
+async fn find_moderated_chat(
+    state: &AppState,
+    chat_id: i32,
+    actor_id: i32,
+) -> Result<chat::Model, Error> {
+    let chat_row = find_accessible_chat(&state.db, chat_id, actor_id).await?;
+    if chat_row.is_direct {
+        return Err(Error::BadRequest("direct chats cannot be moderated"));
+    }
+    Ok(chat_row)
+}
+
+/// Checks that the actor is a moderator or owner and outranks the target.
+async fn authorize(
+    state: &AppState,
+    chat_id: i32,
+    actor_id: i32,
+    target_id: i32,
+) -> Result<chat::Model, Error> {
+    if actor_id == target_id {
+        return Err(Error::BadRequest("cannot moderate yourself"));
+    }
+
+    let chat_row = find_moderated_chat(state, chat_id, actor_id).await?;
+
+    user::Entity::find_by_id(target_id)
+        .one(&state.db)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    let actor_role = member_role(&state.db, &chat_row, actor_id).await?;
+    let target_role = member_role(&state.db, &chat_row, target_id).await?;
+    if actor_role < MemberRole::Moderator || target_role >= actor_role {
+        return Err(Error::Forbidden);
+    }
+
+    Ok(chat_row)
+}
+
+fn expiry(duration_secs: Option<i64>) -> Result<Option<NaiveDateTime>, Error> {
+    match duration_secs {
+        None => Ok(None),
+        Some(secs) if secs > 0 => Utc::now()
+            .checked_add_signed(Duration::seconds(secs))
+            .map(|t| Some(t.naive_utc()))
+            .ok_or(Error::BadRequest("duration is too long")),
+        Some(_) => Err(Error::BadRequest("duration must be positive")),
+    }
+}
+
+async fn apply_sanction(
+    db: &impl ConnectionTrait,
+    chat_id: i32,
+    actor_id: i32,
+    user_id: i32,
+    kind: SanctionKind,
+    expires_at: Option<NaiveDateTime>,
+) -> Result<(), Error> {
+    let sanction = chat_sanction::ActiveModel {
+        chat_id: Set(chat_id),
+        user_id: Set(user_id),
+        kind: Set(kind),
+        expires_at: Set(expires_at),
+        created_by: Set(Some(actor_id)),
+        ..Default::default()
+    };
+    chat_sanction::Entity::insert(sanction)
+        .on_conflict(
+            OnConflict::columns([
+                chat_sanction::Column::ChatId,
+                chat_sanction::Column::UserId,
+                chat_sanction::Column::Kind,
+            ])
+            .update_columns([
+                chat_sanction::Column::ExpiresAt,
+                chat_sanction::Column::CreatedBy,
+            ])
+            .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+
+    Ok(())
+}
+
+async fn lift_sanction(
+    db: &impl ConnectionTrait,
+    chat_id: i32,
+    user_id: i32,
+    kind: SanctionKind,
+) -> Result<(), Error> {
+    chat_sanction::Entity::delete_many()
+        .filter(chat_sanction::Column::ChatId.eq(chat_id))
+        .filter(chat_sanction::Column::UserId.eq(user_id))
+        .filter(chat_sanction::Column::Kind.eq(kind))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+async fn record_action(
+    db: &impl ConnectionTrait,
+    chat_id: i32,
+    actor_id: i32,
+    target_id: i32,
+    action: ModerationAction,
+    reason: Option<String>,
+    expires_at: Option<NaiveDateTime>,
+) -> Result<(), Error> {
+    moderation_log::ActiveModel {
+        chat_id: Set(chat_id),
+        actor_id: Set(Some(actor_id)),
+        target_id: Set(target_id),
+        action: Set(action),
+        reason: Set(reason),
+        expires_at: Set(expires_at),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Tells every instance to drop the user's sockets in this room.
+async fn publish_removed(
+    state: &AppState,
+    chat_id: i32,
+    user_id: i32,
+    action: ModerationAction,
+    reason: Option<String>,
+) {
+    let event = OutgoingMessage::MemberRemoved {
+        chat_id,
+        user_id,
+        action,
+        reason,
+    };
+    publish_event(state, chat_id, &event).await;
+    // The target may not have the room open, so tell them directly as well.
+    publish_to_user(state, user_id, &event).await;
+}
+
+async fn publish_mute_changed(
+    state: &AppState,
+    chat_id: i32,
+    user_id: i32,
+    muted: bool,
+    expires_at: Option<NaiveDateTime>,
+) {
+    let event = OutgoingMessage::MemberMuteChanged {
+        chat_id,
+        user_id,
+        muted,
+        expires_at,
+    };
+    publish_event(state, chat_id, &event).await;
+}
@@ -0,0 +1,453 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use chrono::Utc;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, sea_query::Expr};
+use tracing::error;
+
+use super::ws_chat::{
+    broadcast_user_list, publish_live, send_leave_notification, update_user_count,
+};
+
+use crate::{
+    AppState,
+    entity::{chat, user},
+    errors::Error,
+    models::{chat::Chat, messages::OutgoingMessage, user::visible_status},
+    routes::notifications::publish_to_user,
+};
+
+// Room presence lives in Redis so that it cannot outlive the process that
+// holds the socket. Every socket in a room owns one entry in the room's
+// `presence:{chat_id}` sorted set, `{user_id}:{connection_id}`, scored with
+// the time it expires at. Sockets push the expiry forward every
+// `HEARTBEAT_INTERVAL`; entries whose socket died with its process are
+// removed by the reaper once they pass `PRESENCE_TTL`.
+//
+// A user counts as present while any of their entries is live, so several
+// tabs on the same room show up once and only the first arrival and last
+// departure are announced.
+//
+// Sockets on the user's own channel, `/ws/me`, are tracked the same way in
+// `presence:me:{user_id}` by connection id, but their comings and goings are
+// not announced. They tell who can be reached with pushes such as unread
+// counts.
+
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+const PRESENCE_TTL: Duration = Duration::from_secs(30);
+const REAPER_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Rooms that may still hold presence entries, so the reaper knows where to
+/// look.
+const PRESENCE_ROOMS_KEY: &str = "presence:rooms";
+const CONNECTION_SEQ_KEY: &str = "presence:connection_seq";
+
+// Both scripts take the room's presence key, the user id, the connection's
+// entry, and the current time in milliseconds. Looking for the user's other
+// entries and changing the set in one step keeps two tabs arriving or leaving
+// at once from both being announced.
+
+/// Adds the entry or pushes its expiry to `ARGV[4]`. Returns 1 if the user
+/// had no live entry before.
+const MARK_PRESENT_SCRIPT: &str = r#"
+local prefix = ARGV[1] .. ':'
+local arrived = 1
+for _, entry in ipairs(redis.call('ZRANGEBYSCORE', KEYS[1], '(' .. ARGV[3], '+inf')) do
+    if string.sub(entry, 1, #prefix) == prefix then
+        arrived = 0
+        break
+    end
+end
+redis.call('ZADD', KEYS[1], ARGV[4], ARGV[2])
+return arrived
+"#;
+
+/// Removes the entry. Returns 1 if it was there and the user has no live
+/// entry left.
+const MARK_ABSENT_SCRIPT: &str = r#"
+if redis.call('ZREM', KEYS[1], ARGV[2]) == 0 then
+    return 0
+end
+local prefix = ARGV[1] .. ':'
+for _, entry in ipairs(redis.call('ZRANGEBYSCORE', KEYS[1], '(' .. ARGV[3], '+inf')) do
+    if string.sub(entry, 1, #prefix) == prefix then
+        return 0
+    end
+end
+return 1
+"#;
+
+fn presence_key(chat_id: i32) -> String {
+    format!("presence:{chat_id}")
+}
+
+/// Rooms the user may be present in. Stale members are pruned on read.
+fn user_rooms_key(user_id: i32) -> String {
+    format!("presence:user:{user_id}")
+}
+
+fn user_sockets_key(user_id: i32) -> String {
+    format!("presence:me:{user_id}")
+}
+
+fn presence_entry(user_id: i32, connection_id: i64) -> String {
+    format!("{user_id}:{connection_id}")
+}
+
+fn entry_user_id(entry: &str) -> Option<i32> {
+    entry.split_once(':')?.0.parse().ok()
+}
+
+fn now_millis() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+/// A cluster-wide unique id for a new socket.
+pub async fn next_connection_id(state: &AppState) -> Result<i64, Error> {
+    state.redis_client.incr(CONNECTION_SEQ_KEY).await
+}
+
+/// Marks the connection present in the room, or keeps it present when called
+/// from the heartbeat. Returns whether the user was not present before, i.e.
+/// whether their arrival should be announced.
+pub(super) async fn mark_present(
+    state: &AppState,
+    chat_id: i32,
+    user_id: i32,
+    connection_id: i64,
+) -> Result<bool, Error> {
+    let expires_at = now_millis() + PRESENCE_TTL.as_millis() as i64;
+    let arrived = state
+        .redis_client
+        .eval(
+            MARK_PRESENT_SCRIPT,
+            &[presence_key(chat_id)],
+            &[
+                user_id.to_string(),
+                presence_entry(user_id, connection_id),
+                now_millis().to_string(),
+                expires_at.to_string(),
+            ],
+        )
+        .await?
+        == 1;
+    // Re-added on every heartbeat in case the reaper dropped the room after
+    // finding it empty.
+    state
+        .redis_client
+        .sadd(PRESENCE_ROOMS_KEY, &chat_id.to_string())
+        .await?;
+    state
+        .redis_client
+        .sadd(&user_rooms_key(user_id), &chat_id.to_string())
+        .await?;
+
+    Ok(arrived)
+}
+
+/// Removes the connection from the room. Returns whether the user is gone
+/// now, i.e. whether their departure should be announced. An entry the
+/// reaper already removed has been announced by it.
+pub(super) async fn mark_absent(
+    state: &AppState,
+    chat_id: i32,
+    user_id: i32,
+    connection_id: i64,
+) -> Result<bool, Error> {
+    let departed = state
+        .redis_client
+        .eval(
+            MARK_ABSENT_SCRIPT,
+            &[presence_key(chat_id)],
+            &[
+                user_id.to_string(),
+                presence_entry(user_id, connection_id),
+                now_millis().to_string(),
+            ],
+        )
+        .await?
+        == 1;
+    if departed {
+        state
+            .redis_client
+            .srem(&user_rooms_key(user_id), &chat_id.to_string())
+            .await?;
+    }
+    Ok(departed)
+}
+
+/// Marks the user's `/ws/me` connection live, or keeps it live when called
+/// from its heartbeat.
+pub async fn mark_user_socket(
+    state: &AppState,
+    user_id: i32,
+    connection_id: i64,
+) -> Result<(), Error> {
+    let key = user_sockets_key(user_id);
+    let expires_at = now_millis() + PRESENCE_TTL.as_millis() as i64;
+    state
+        .redis_client
+        .zadd(&key, &connection_id.to_string(), expires_at)
+        .await?;
+    // Entries are not reaped one by one; the key goes once every socket on it
+    // has stopped beating.
+    state
+        .redis_client
+        .expire(&key, PRESENCE_TTL.as_secs() as usize)
+        .await
+}
+
+pub async fn clear_user_socket(
+    state: &AppState,
+    user_id: i32,
+    connection_id: i64,
+) -> Result<(), Error> {
+    state
+        .redis_client
+        .zrem(&user_sockets_key(user_id), &connection_id.to_string())
+        .await?;
+    Ok(())
+}
+
+/// Those of `user_ids` with a live `/ws/me` socket, in the given order.
+pub(super) async fn with_user_socket(
+    state: &AppState,
+    user_ids: &[i32],
+) -> Result<Vec<i32>, Error> {
+    if user_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let keys: Vec<String> = user_ids.iter().map(|&id| user_sockets_key(id)).collect();
+    let entries = state
+        .redis_client
+        .zrangebyscore_many(&keys, &format!("({}", now_millis()), "+inf")
+        .await?;
+
+    Ok(user_ids
+        .iter()
+        .zip(entries)
+        .filter(|(_, entries)| !entries.is_empty())
+        .map(|(&user_id, _)| user_id)
+        .collect())
+}
+
+/// Whether the user has any live socket, in a room or on their own channel.
+pub async fn is_online(state: &AppState, user_id: i32) -> Result<bool, Error> {
+    if !with_user_socket(state, &[user_id]).await?.is_empty() {
+        return Ok(true);
+    }
+    Ok(!rooms_of(state, user_id).await?.is_empty())
+}
+
+/// Rooms the user currently has a live connection in.
+pub(super) async fn rooms_of(state: &AppState, user_id: i32) -> Result<Vec<i32>, Error> {
+    let key = user_rooms_key(user_id);
+    let chat_ids: Vec<i32> = state
+        .redis_client
+        .smembers(&key)
+        .await?
+        .iter()
+        .filter_map(|id| id.parse().ok())
+        .collect();
+    if chat_ids.is_empty() {
+        return Ok(chat_ids);
+    }
+
+    let keys: Vec<String> = chat_ids.iter().map(|&id| presence_key(id)).collect();
+    let entries = state
+        .redis_client
+        .zrangebyscore_many(&keys, &format!("({}", now_millis()), "+inf")
+        .await?;
+
+    let mut rooms = Vec::new();
+    for (chat_id, entries) in chat_ids.into_iter().zip(entries) {
+        if distinct_users(&entries).contains(&user_id) {
+            rooms.push(chat_id);
+        } else {
+            state.redis_client.srem(&key, &chat_id.to_string()).await?;
+        }
+    }
+    Ok(rooms)
+}
+
+/// Users with at least one live connection in the room, in id order.
+pub(super) async fn live_user_ids(state: &AppState, chat_id: i32) -> Result<Vec<i32>, Error> {
+    let entries = state
+        .redis_client
+        .zrangebyscore(
+            &presence_key(chat_id),
+            &format!("({}", now_millis()),
+            "+inf",
+        )
+        .await?;
+    Ok(distinct_users(&entries))
+}
+
+/// Fills in `active_users` for each listed room.
+pub(super) async fn attach_active_users(state: &AppState, chats: &mut [Chat]) -> Result<(), Error> {
+    if chats.is_empty() {
+        return Ok(());
+    }
+
+    let keys: Vec<String> = chats.iter().map(|c| presence_key(c.id)).collect();
+    let entries = state
+        .redis_client
+        .zrangebyscore_many(&keys, &format!("({}", now_millis()), "+inf")
+        .await?;
+
+    for (chat, entries) in chats.iter_mut().zip(entries) {
+        chat.active_users = distinct_users(&entries).len() as i64;
+    }
+    Ok(())
+}
+
+fn distinct_users(entries: &[String]) -> Vec<i32> {
+    entries
+        .iter()
+        .filter_map(|entry| entry_user_id(entry))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Periodically removes expired presence entries and announces the users who
+/// thereby left. Every instance runs one; removing an entry is what claims it,
+/// so each departure is announced once.
+pub fn spawn_presence_reaper(state: AppState) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(REAPER_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = reap_expired(&state).await {
+                error!("presence reaper failed: {e:?}");
+            }
+        }
+    });
+}
+
+async fn reap_expired(state: &AppState) -> Result<(), Error> {
+    let rooms = state.redis_client.smembers(PRESENCE_ROOMS_KEY).await?;
+
+    for room in rooms {
+        let Ok(chat_id) = room.parse::<i32>() else {
+            continue;
+        };
+        let key = presence_key(chat_id);
+
+        let expired = state
+            .redis_client
+            .zrangebyscore(&key, "-inf", &now_millis().to_string())
+            .await?;
+
+        let mut reaped = BTreeSet::new();
+        for entry in &expired {
+            if state.redis_client.zrem(&key, entry).await? {
+                reaped.extend(entry_user_id(entry));
+            }
+        }
+
+        if !reaped.is_empty() {
+            let live = live_user_ids(state, chat_id).await?;
+            let departed: Vec<i32> = reaped
+                .into_iter()
+                .filter(|user_id| !live.contains(user_id))
+                .collect();
+            if !departed.is_empty() {
+                announce_departures(state, chat_id, &departed).await?;
+            }
+        }
+
+        if state.redis_client.zcard(&key).await? == 0 {
+            state.redis_client.srem(PRESENCE_ROOMS_KEY, &room).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn announce_departures(
+    state: &AppState,
+    chat_id: i32,
+    user_ids: &[i32],
+) -> Result<(), Error> {
+    let Some(chat_row) = chat::Entity::find_by_id(chat_id).one(&state.db).await? else {
+        return Ok(());
+    };
+
+    let users = user::Entity::find()
+        .filter(user::Column::Id.is_in(user_ids.iter().copied()))
+        .all(&state.db)
+        .await?;
+
+    for departed in &users {
+        state
+            .redis_client
+            .srem(&user_rooms_key(departed.id), &chat_id.to_string())
+            .await?;
+        send_leave_notification(state, chat_id, departed.id, &departed.username).await;
+    }
+    // Their socket died without saying goodbye, so this is the disconnect.
+    touch_last_seen(state, user_ids).await?;
+
+    if !chat_row.is_direct && !chat_row.is_private {
+        update_user_count(state, chat_id).await;
+    }
+    broadcast_user_list(state, chat_id).await;
+    Ok(())
+}
+
+/// Records that the users were just seen, e.g. when their socket closes.
+pub(super) async fn touch_last_seen(state: &AppState, user_ids: &[i32]) -> Result<(), Error> {
+    user::Entity::update_many()
+        .col_expr(
+            user::Column::LastSeenAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .filter(user::Column::Id.is_in(user_ids.iter().copied()))
+        .exec(&state.db)
+        .await?;
+    Ok(())
+}
+
+/// Tells every room the user is present in, and the user's own sockets, that
+/// their status changed. Rooms also get a fresh `user_list`, which invisible
+/// users drop out of.
+pub async fn broadcast_user_status(state: &AppState, user_row: &user::Model) {
+    let rooms = rooms_of(state, user_row.id).await.unwrap_or_else(|e| {
+        error!("failed to list rooms of user {}: {e:?}", user_row.id);
+        Vec::new()
+    });
+
+    let (status, status_message) = visible_status(user_row, true);
+    for chat_id in rooms {
+        let event = OutgoingMessage::UserStatus {
+            chat_id: Some(chat_id),
+            user_id: user_row.id,
+            status,
+            status_message: status_message.clone(),
+        };
+        publish_live(state, chat_id, &event).await;
+        broadcast_user_list(state, chat_id).await;
+    }
+
+    let own = OutgoingMessage::UserStatus {
+        chat_id: None,
+        user_id: user_row.id,
+        status: user_row.status,
+        status_message: user_row.status_message.clone(),
+    };
+    publish_to_user(state, user_row.id, &own).await;
+}
+
+/// Re-sends `user_list` to every room the user is present in, e.g. after their
+/// profile changed.
+pub async fn refresh_user_lists(state: &AppState, user_id: i32) {
+    match rooms_of(state, user_id).await {
+        Ok(rooms) => {
+            for chat_id in rooms {
+                broadcast_user_list(state, chat_id).await;
+            }
+        }
+        Err(e) => error!("failed to list rooms of user {user_id}: {e:?}"),
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, FromQueryResult, Order, QueryFilter,
+    QueryOrder, QuerySelect, sea_query::Expr,
+};
+
+use crate::{
+    entity::reaction,
+    models::messages::{MessagePayload, ReactionCount, ReactionSummary},
+};
+
+#[derive(FromQueryResult)]
+struct ReactionRow {
+    message_id: i32,
+    emoji: String,
+    count: i64,
+    reacted: bool,
+}
+
+/// Aggregates reactions for the given messages, flagging the emojis `user_id`
+/// has reacted with.
+async fn load_reaction_rows(
+    db: &DatabaseConnection,
+    message_ids: Vec<i32>,
+    user_id: i32,
+) -> Result<Vec<ReactionRow>, DbErr> {
+    if message_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    reaction::Entity::find()
+        .filter(reaction::Column::MessageId.is_in(message_ids))
+        .select_only()
+        .column(reaction::Column::MessageId)
+        .column(reaction::Column::Emoji)
+        .column_as(Expr::col(reaction::Column::Id).count(), "count")
+        .column_as(
+            Expr::cust_with_values("BOOL_OR(\"reaction\".\"user_id\" = $1)", [user_id]),
+            "reacted",
+        )
+        .group_by(reaction::Column::MessageId)
+        .group_by(reaction::Column::Emoji)
+        .order_by(Expr::col(reaction::Column::Id).min(), Order::Asc)
+        .into_model::<ReactionRow>()
+        .all(db)
+        .await
+}
+
+/// Fills in `reactions` on each message for the requesting user.
+pub async fn attach_reactions(
+    db: &DatabaseConnection,
+    messages: &mut [MessagePayload],
+    user_id: i32,
+) -> Result<(), DbErr> {
+    let ids = messages.iter().map(|m| m.id).collect();
+    let mut by_message: HashMap<i32, Vec<ReactionSummary>> = HashMap::new();
+    for row in load_reaction_rows(db, ids, user_id).await? {
+        by_message
+            .entry(row.message_id)
+            .or_default()
+            .push(ReactionSummary {
+                emoji: row.emoji,
+                count: row.count,
+                reacted: row.reacted,
+            });
+    }
+
+    for m in messages.iter_mut() {
+        m.reactions = by_message.remove(&m.id).unwrap_or_default();
+    }
+
+    Ok(())
+}
+
+/// Reaction totals for a single message, as broadcast to the whole room.
+pub async fn reaction_counts(
+    db: &DatabaseConnection,
+    message_id: i32,
+) -> Result<Vec<ReactionCount>, DbErr> {
+    Ok(load_reaction_rows(db, vec![message_id], 0)
+        .await?
+        .into_iter()
+        .map(|row| ReactionCount {
+            emoji: row.emoji,
+            count: row.count,
+        })
+        .collect())
+}
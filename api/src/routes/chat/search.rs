@@ -0,0 +1,228 @@
+use axum::{
+    Extension, Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use sea_orm::{
+    ColumnTrait, EntityTrait, JoinType, Order, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect, RelationTrait, sea_query::Expr,
+};
+
+use crate::{
+    AppState,
+    entity::{chat, message, user},
+    errors::Error,
+    models::{
+        chat::{
+            Chat, ChatSearchQuery, ChatSearchResponse, MessageSearchQuery, MessageSearchResult,
+        },
+        claims::Claims,
+    },
+};
+
+use super::{
+    access::{find_accessible_chat, listed_for, readable_by},
+    chat::chat_summaries,
+    presence::attach_active_users,
+};
+
+const DEFAULT_SEARCH_LIMIT: u64 = 20;
+const MAX_SEARCH_LIMIT: u64 = 50;
+const MAX_QUERY_CHARS: usize = 200;
+const DEFAULT_CHAT_PAGE_SIZE: u64 = 20;
+const MAX_CHAT_PAGE_SIZE: u64 = 100;
+
+/// Room names that are trigram-similar to `$1` or contain it as a substring.
+/// `$2` is `$1` with its LIKE wildcards escaped.
+const CHAT_NAME_MATCH: &str = "(chat.name % $1 OR chat.name ILIKE '%' || $2 || '%')";
+
+// `ts_headline` works on raw message text, so it brackets matches with
+// control characters that are first stripped from the content. The snippet
+// is HTML-escaped before they become `<mark>` tags.
+const SNIPPET: &str = "ts_headline('english', \
+     translate(message.content, E'\\u0002\\u0003', ''), \
+     websearch_to_tsquery('english', $1), \
+     E'StartSel=\\u0002, StopSel=\\u0003, MaxFragments=2')";
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+pub async fn search_chats(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<ChatSearchQuery>,
+) -> Result<(StatusCode, Json<ChatSearchResponse>), Error> {
+    let q = validate_query(&params.q)?;
+    let pattern = escape_like(q);
+
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_CHAT_PAGE_SIZE)
+        .clamp(1, MAX_CHAT_PAGE_SIZE);
+    let offset = params.offset.unwrap_or(0);
+
+    let total = chat::Entity::find()
+        .filter(listed_for(claims.sub))
+        .filter(Expr::cust_with_values(
+            CHAT_NAME_MATCH,
+            [q, pattern.as_str()],
+        ))
+        .count(&state.db)
+        .await?;
+
+    let mut chats = chat_summaries(claims.sub)
+        .filter(Expr::cust_with_values(
+            CHAT_NAME_MATCH,
+            [q, pattern.as_str()],
+        ))
+        .order_by(
+            Expr::cust_with_values("similarity(chat.name, $1)", [q]),
+            Order::Desc,
+        )
+        .order_by_asc(chat::Column::Id)
+        .limit(limit)
+        .offset(offset)
+        .into_model::<Chat>()
+        .all(&state.db)
+        .await?;
+    attach_active_users(&state, &mut chats).await?;
+
+    Ok((StatusCode::OK, Json(ChatSearchResponse { chats, total })))
+}
+
+pub async fn search_messages(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<MessageSearchQuery>,
+) -> Result<(StatusCode, Json<Vec<MessageSearchResult>>), Error> {
+    let q = validate_query(&params.q)?;
+
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
+
+    let mut query = message::Entity::find()
+        .join(JoinType::InnerJoin, message::Relation::User.def())
+        .join(JoinType::InnerJoin, message::Relation::Chat.def())
+        .select_only()
+        .column(message::Column::Id)
+        .column(message::Column::ChatId)
+        .column_as(chat::Column::Name, "chat_name")
+        .column(message::Column::SenderId)
+        .column_as(user::Column::Username, "username")
+        .column_as(Expr::cust_with_values(SNIPPET, [q]), "snippet")
+        .column(message::Column::CreatedAt)
+        .filter(Expr::cust_with_values(
+            "message.search_vector @@ websearch_to_tsquery('english', $1)",
+            [q],
+        ))
+        .filter(message::Column::DeletedAt.is_null())
+        .filter(readable_by(claims.sub));
+
+    if let Some(chat_id) = params.chat_id {
+        find_accessible_chat(&state.db, chat_id, claims.sub).await?;
+        query = query.filter(message::Column::ChatId.eq(chat_id));
+    }
+    if let Some(from) = params.from {
+        query = query.filter(message::Column::CreatedAt.gte(from));
+    }
+    if let Some(before) = params.before {
+        query = query.filter(message::Column::CreatedAt.lt(before));
+    }
+
+    let mut results = query
+        .order_by(
+            Expr::cust_with_values(
+                "ts_rank(message.search_vector, websearch_to_tsquery('english', $1))",
+                [q],
+            ),
+            Order::Desc,
+        )
+        .order_by_desc(message::Column::Id)
+        .limit(limit)
+        .into_model::<MessageSearchResult>()
+        .all(&state.db)
+        .await?;
+    for result in &mut results {
+        result.snippet = render_snippet(&result.snippet);
+    }
+
+    Ok((StatusCode::OK, Json(results)))
+}
+
+fn validate_query(q: &str) -> Result<&str, Error> {
+    let q = q.trim();
+    if q.is_empty() {
+        return Err(Error::BadRequest("search query is empty"));
+    }
+    if q.chars().count() > MAX_QUERY_CHARS {
+        return Err(Error::BadRequest("search query is too long"));
+    }
+    Ok(q)
+}
+
+/// Turns a headline from `SNIPPET` into HTML: the message text is escaped and
+/// only the matches are wrapped in `<mark>`.
+fn render_snippet(headline: &str) -> String {
+    let mut html = String::with_capacity(headline.len());
+    for c in headline.chars() {
+        match c {
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_like_metacharacters() {
+        assert_eq!(escape_like("100%"), r"100\%");
+        assert_eq!(escape_like("snake_case"), r"snake\_case");
+        assert_eq!(escape_like(r"C:\temp"), r"C:\\temp");
+        assert_eq!(escape_like(r"\%_"), r"\\\%\_");
+    }
+
+    #[test]
+    fn leaves_other_text_alone() {
+        assert_eq!(escape_like("héllo wörld*?"), "héllo wörld*?");
+    }
+
+    #[test]
+    fn snippets_escape_message_html() {
+        assert_eq!(
+            render_snippet("<script>alert('\u{2}hi\u{3}')</script> & <img onerror=\"x\">"),
+            "&lt;script&gt;alert(&#39;<mark>hi</mark>&#39;)&lt;/script&gt; &amp; \
+             &lt;img onerror=&quot;x&quot;&gt;"
+        );
+        assert_eq!(
+            render_snippet("<mark>fake</mark>"),
+            "&lt;mark&gt;fake&lt;/mark&gt;"
+        );
+    }
+
+    #[test]
+    fn query_length_is_bounded_in_characters_after_trimming() {
+        assert!(validate_query("   ").is_err());
+        assert_eq!(validate_query("  hi  ").ok(), Some("hi"));
+        let longest = "é".repeat(MAX_QUERY_CHARS);
+        assert!(validate_query(&longest).is_ok());
+        assert!(validate_query(&format!("{longest}é")).is_err());
+    }
+}
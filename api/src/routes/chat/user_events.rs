@@ -0,0 +1,163 @@
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, JoinType, QueryFilter, QuerySelect,
+    QueryTrait,
+    sea_query::{Alias, Expr},
+};
+use tracing::error;
+
+use super::presence::with_user_socket;
+
+use crate::{
+    AppState,
+    entity::{chat, chat_member, direct_chat, message, read_receipt, user},
+    errors::Error,
+    models::messages::{MessagePayload, OutgoingMessage},
+    routes::notifications::publish_to_user,
+};
+
+/// Messages in the room that each of `user_ids` has not read yet, in one
+/// grouped query. Their own messages and deleted ones never count.
+pub async fn unread_counts(
+    db: &DatabaseConnection,
+    chat_id: i32,
+    user_ids: &[i32],
+) -> Result<Vec<(i32, u64)>, DbErr> {
+    if user_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut query = user::Entity::find()
+        .select_only()
+        .column(user::Column::Id)
+        .column_as(Expr::cust("COUNT(unread.id)"), "unread_count")
+        .filter(user::Column::Id.is_in(user_ids.iter().copied()))
+        .group_by(user::Column::Id);
+    QueryTrait::query(&mut query)
+        .join_as(
+            JoinType::LeftJoin,
+            read_receipt::Entity,
+            Alias::new("seen"),
+            Expr::cust_with_values(
+                "seen.chat_id = $1 AND seen.user_id = \"user\".id",
+                [chat_id],
+            ),
+        )
+        .join_as(
+            JoinType::LeftJoin,
+            message::Entity,
+            Alias::new("unread"),
+            Expr::cust_with_values(
+                "unread.chat_id = $1 AND unread.sender_id <> \"user\".id \
+                 AND unread.deleted_at IS NULL \
+                 AND unread.id > COALESCE(seen.last_read_message_id, 0)",
+                [chat_id],
+            ),
+        );
+
+    let rows: Vec<(i32, i64)> = query.into_tuple().all(db).await?;
+    Ok(rows
+        .into_iter()
+        .map(|(user_id, count)| (user_id, count as u64))
+        .collect())
+}
+
+pub async fn push_unread_count(state: &AppState, chat_id: i32, user_id: i32) {
+    match unread_counts(&state.db, chat_id, &[user_id]).await {
+        Ok(counts) => {
+            for (user_id, unread_count) in counts {
+                let event = OutgoingMessage::UnreadCount {
+                    chat_id,
+                    unread_count,
+                };
+                publish_to_user(state, user_id, &event).await;
+            }
+        }
+        Err(e) => error!("failed to count unread messages: {e:?}"),
+    }
+}
+
+/// Tells the room's audience about a new message on their user channels, in
+/// the background. Only users with a `/ws/me` socket open somewhere are
+/// counted for and pushed to; the others see fresh counts when they next
+/// list their rooms.
+pub fn notify_room_members(state: &AppState, message: &MessagePayload) {
+    let state = state.clone();
+    let message = message.clone();
+    tokio::spawn(async move {
+        if let Err(e) = fan_out(&state, &message).await {
+            error!(
+                "failed to notify members of room {}: {e:?}",
+                message.chat_id
+            );
+        }
+    });
+}
+
+async fn fan_out(state: &AppState, message: &MessagePayload) -> Result<(), Error> {
+    let Some((is_direct, user_ids)) = room_audience(&state.db, message.chat_id).await? else {
+        return Ok(());
+    };
+    let user_ids: Vec<i32> = user_ids
+        .into_iter()
+        .filter(|&user_id| user_id != message.sender_id)
+        .collect();
+    let reachable = with_user_socket(state, &user_ids).await?;
+
+    if is_direct {
+        for &user_id in &reachable {
+            publish_to_user(
+                state,
+                user_id,
+                &OutgoingMessage::DirectMessage(message.clone()),
+            )
+            .await;
+        }
+    }
+
+    for (user_id, unread_count) in unread_counts(&state.db, message.chat_id, &reachable).await? {
+        let event = OutgoingMessage::UnreadCount {
+            chat_id: message.chat_id,
+            unread_count,
+        };
+        publish_to_user(state, user_id, &event).await;
+    }
+    Ok(())
+}
+
+/// Who keeps track of a room's unread messages, along with whether it is a
+/// direct conversation: its members, or for public rooms everyone who has
+/// read it before.
+async fn room_audience(
+    db: &DatabaseConnection,
+    chat_id: i32,
+) -> Result<Option<(bool, Vec<i32>)>, DbErr> {
+    let Some(chat_row) = chat::Entity::find_by_id(chat_id).one(db).await? else {
+        return Ok(None);
+    };
+
+    if chat_row.is_direct {
+        let pair = direct_chat::Entity::find_by_id(chat_id).one(db).await?;
+        return Ok(pair.map(|p| (true, vec![p.user_low_id, p.user_high_id])));
+    }
+
+    if chat_row.is_private {
+        let members: Vec<i32> = chat_member::Entity::find()
+            .select_only()
+            .column(chat_member::Column::UserId)
+            .filter(chat_member::Column::ChatId.eq(chat_id))
+            .filter(chat_member::Column::JoinedAt.is_not_null())
+            .into_tuple()
+            .all(db)
+            .await?;
+        return Ok(Some((false, members)));
+    }
+
+    let readers: Vec<i32> = read_receipt::Entity::find()
+        .select_only()
+        .column(read_receipt::Column::UserId)
+        .filter(read_receipt::Column::ChatId.eq(chat_id))
+        .into_tuple()
+        .all(db)
+        .await?;
+    Ok(Some((false, readers)))
+}
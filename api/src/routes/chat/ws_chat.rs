@@ -1,242 +1,763 @@
-use std::{collections::HashMap, sync::Arc};
-
 use axum::{
     Extension,
     extract::{
         Query, State,
-        ws::{Message, WebSocket, WebSocketUpgrade},
+        ws::{Message, WebSocketUpgrade},
     },
     response::IntoResponse,
 };
-use futures::{SinkExt, StreamExt, lock::Mutex, stream::SplitSink};
+use chrono::Utc;
+use futures::SinkExt;
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter,
+    ActiveModelTrait,
+    ActiveValue::Set,
+    ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    sea_query::{Expr, OnConflict},
+};
+use tracing::{error, warn};
+
+use super::{
+    access::{find_accessible_chat, has_active_sanction},
+    mentions::notify_mentions,
+    presence::live_user_ids,
+    reactions::reaction_counts,
+    user_events::{notify_room_members, push_unread_count},
+    ws_session::{SessionMode, SocketSink, TypingState, run_session},
 };
-use tracing::error;
 
 use crate::{
     AppState,
     clients::ChatMessage,
-    entity::{message, online_user, user},
+    entity::{
+        chat_sanction::SanctionKind,
+        message, reaction, read_receipt,
+        user::{self, UserStatus},
+    },
+    errors::Error,
     models::{
+        chat::ChatSocketParams,
         claims::Claims,
-        messages::{IncomingMessage, OutgoingMessage},
+        messages::{
+            IncomingMessage, MessagePayload, OnlineUserEntry, OutgoingMessage, SystemMessageKind,
+        },
+        user::visible_status,
     },
 };
 
-#[derive(serde::Deserialize)]
-struct RecentEntry {
-    username: String,
-    content: String,
-}
+const MAX_MESSAGE_CHARS: usize = 4000;
+// An update may retry when another one changed the entry in the meantime.
+const MAX_RECENT_MESSAGE_UPDATE_ATTEMPTS: usize = 5;
+
+// The recent-message cache is changed by several sockets at once while new
+// messages are pushed onto it, so entries are never addressed by a list index
+// read beforehand.
+
+/// Replaces the entry that is still exactly `ARGV[1]` with `ARGV[2]`.
+/// Returns 0 if that entry changed or left the list since it was read.
+const REPLACE_RECENT_MESSAGE_SCRIPT: &str = r#"
+for index, entry in ipairs(redis.call('LRANGE', KEYS[1], 0, -1)) do
+    if entry == ARGV[1] then
+        redis.call('LSET', KEYS[1], index - 1, ARGV[2])
+        return 1
+    end
+end
+return 0
+"#;
+
+/// Removes the entry for the message with id `ARGV[1]`, whatever it looks
+/// like by now.
+const REMOVE_RECENT_MESSAGE_SCRIPT: &str = r#"
+local id = tonumber(ARGV[1])
+for _, entry in ipairs(redis.call('LRANGE', KEYS[1], 0, -1)) do
+    local ok, message = pcall(cjson.decode, entry)
+    if ok and type(message) == 'table' and message.id == id then
+        return redis.call('LREM', KEYS[1], 1, entry)
+    end
+end
+return 0
+"#;
 
 pub async fn chat_ws(
     Extension(claims): Extension<Claims>,
     State(state): State<AppState>,
     ws: WebSocketUpgrade,
-    Query(params): Query<HashMap<String, String>>,
+    Query(params): Query<ChatSocketParams>,
+) -> Result<impl IntoResponse, Error> {
+    if params
+        .last_event_id
+        .as_deref()
+        .is_some_and(|id| !is_event_id(id))
+    {
+        return Err(Error::BadRequest("invalid last_event_id"));
+    }
+    let chat_row = find_accessible_chat(&state.db, params.chat_id, claims.sub).await?;
+
+    Ok(ws.on_upgrade(move |socket| {
+        run_session(
+            socket,
+            state,
+            claims,
+            SessionMode::Single(chat_row, params.last_event_id),
+        )
+    }))
+}
+
+/// Whether `id` has the `<millis>-<sequence>` shape of a stream event id.
+pub(super) fn is_event_id(id: &str) -> bool {
+    // `u64::from_str` also takes a leading `+`, which Redis does not.
+    let is_part =
+        |part: &str| part.starts_with(|c: char| c.is_ascii_digit()) && part.parse::<u64>().is_ok();
+    id.split_once('-')
+        .is_some_and(|(millis, sequence)| is_part(millis) && is_part(sequence))
+}
+
+/// One socket for many rooms: the client joins and leaves rooms with
+/// `subscribe`/`unsubscribe` frames and addresses room frames by `chat_id`.
+pub async fn rooms_ws(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    ws: WebSocketUpgrade,
 ) -> impl IntoResponse {
-    let chat_id: i32 = params
-        .get("chat_id")
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(0);
-    let user_id: i32 = claims.sub;
-    let username = claims.username.clone();
-    let db = state.db.clone();
-
-    ws.on_upgrade(move |socket| async move {
-        let _ = online_user::ActiveModel {
-            user_id: Set(user_id),
-            chat_id: Set(chat_id),
-            ..Default::default()
+    ws.on_upgrade(move |socket| run_session(socket, state, claims, SessionMode::Multiplexed))
+}
+
+pub(super) async fn handle_incoming(
+    state: &AppState,
+    chat_id: i32,
+    username: &str,
+    user_id: i32,
+    tx: &SocketSink,
+    typing: &mut TypingState,
+    incoming_message: IncomingMessage,
+) {
+    let result = match incoming_message {
+        IncomingMessage::ChatMessage { content, reply_to } => {
+            typing.stop(state, chat_id, user_id, username).await;
+            handle_chat_message(state, chat_id, username, user_id, content, reply_to).await
+        }
+        IncomingMessage::Typing => {
+            typing.refresh(state, chat_id, user_id, username).await;
+            Ok(())
+        }
+        IncomingMessage::RequestSuggestion { current_input } => {
+            handle_suggestion_request(state.clone(), chat_id, &current_input, tx).await;
+            Ok(())
         }
-        .insert(&db)
-        .await;
+        IncomingMessage::EditMessage {
+            message_id,
+            content,
+        } => handle_edit_message(state, chat_id, user_id, message_id, content).await,
+        IncomingMessage::DeleteMessage { message_id } => {
+            handle_delete_message(state, chat_id, user_id, message_id).await
+        }
+        IncomingMessage::AddReaction { message_id, emoji } => {
+            handle_reaction(state, chat_id, user_id, message_id, emoji, true).await
+        }
+        IncomingMessage::RemoveReaction { message_id, emoji } => {
+            handle_reaction(state, chat_id, user_id, message_id, emoji, false).await
+        }
+        IncomingMessage::MarkRead { message_id } => {
+            handle_mark_read(state, chat_id, user_id, username, message_id).await
+        }
+    };
+
+    if let Err(e) = result {
+        send_error(tx, Some(chat_id), e).await;
+    }
+}
+
+/// Rejects message content that is blank or longer than `MAX_MESSAGE_CHARS`.
+fn check_content(content: &str) -> Result<(), &'static str> {
+    if content.trim().is_empty() {
+        return Err("message cannot be empty");
+    }
+    if content.chars().count() > MAX_MESSAGE_CHARS {
+        return Err("message is too long");
+    }
+    Ok(())
+}
 
-        handle_socket(socket, state.clone(), chat_id, username.clone(), user_id).await;
+async fn handle_chat_message(
+    state: &AppState,
+    chat_id: i32,
+    username: &str,
+    user_id: i32,
+    content: String,
+    reply_to: Option<i32>,
+) -> Result<(), &'static str> {
+    check_content(&content)?;
+    if has_active_sanction(&state.db, chat_id, user_id, SanctionKind::Mute)
+        .await
+        .map_err(|_| "something went wrong")?
+    {
+        return Err("you are muted in this room");
+    }
 
-        let _ = online_user::Entity::delete_many()
-            .filter(online_user::Column::UserId.eq(user_id))
-            .filter(online_user::Column::ChatId.eq(chat_id))
-            .exec(&db)
-            .await;
+    let parent_id = match reply_to {
+        Some(parent_id) => Some(find_thread_root(state, chat_id, parent_id).await?),
+        None => None,
+    };
+
+    let message = message::ActiveModel {
+        chat_id: Set(chat_id),
+        sender_id: Set(user_id),
+        content: Set(content.clone()),
+        parent_id: Set(parent_id),
+        ..Default::default()
+    };
+    let inserted = message.insert(&state.db).await.map_err(|e| {
+        error!("failed to persist chat message: {e:?}");
+        "something went wrong"
+    })?;
+    let sender = user::Entity::find_by_id(user_id)
+        .one(&state.db)
+        .await
+        .ok()
+        .flatten();
+
+    let payload = MessagePayload {
+        id: inserted.id,
+        chat_id,
+        sender_id: user_id,
+        username: username.to_string(),
+        display_name: sender.as_ref().and_then(|u| u.display_name.clone()),
+        avatar_url: sender.and_then(|u| u.avatar_url),
+        content: inserted.content,
+        created_at: inserted.created_at,
+        edited_at: None,
+        deleted_at: None,
+        parent_id,
+        reply_count: 0,
+        reactions: Vec::new(),
+    };
+
+    // Replies live in their thread, so only top-level messages enter the
+    // room's recent-message cache; the parent's reply count is bumped instead.
+    if let Some(parent_id) = parent_id {
+        update_recent_message(state, chat_id, parent_id, |entry| entry.reply_count += 1).await;
+        publish_event(state, chat_id, &OutgoingMessage::Message(payload.clone())).await;
+        notify_mentions(state, &payload).await;
+        notify_room_members(state, &payload);
+        return Ok(());
+    }
+
+    let redis_client = &state.redis_client;
+    let redis_messages_key = format!("chat_messages:{chat_id}");
+    if let Ok(recent_msg) = serde_json::to_string(&payload) {
+        let _ = redis_client
+            .lpush(&redis_messages_key, recent_msg)
+            .await
+            .unwrap_or(0);
+        redis_client
+            .ltrim(&redis_messages_key, 0, 99)
+            .await
+            .unwrap_or(());
+    }
+
+    publish_event(state, chat_id, &OutgoingMessage::Message(payload.clone())).await;
+    notify_mentions(state, &payload).await;
+    notify_room_members(state, &payload);
+    Ok(())
+}
 
-        send_leave_notification(&state, chat_id, &username).await;
-        update_user_count(&state, chat_id).await;
-        broadcast_user_list(&state, chat_id).await;
+/// Resolves the message a reply targets to the root of its thread, so replies
+/// to replies stay in the same flat thread.
+async fn find_thread_root(
+    state: &AppState,
+    chat_id: i32,
+    message_id: i32,
+) -> Result<i32, &'static str> {
+    let parent = message::Entity::find_by_id(message_id)
+        .filter(message::Column::ChatId.eq(chat_id))
+        .filter(message::Column::DeletedAt.is_null())
+        .one(&state.db)
+        .await
+        .map_err(|_| "something went wrong")?
+        .ok_or("message not found")?;
+
+    Ok(parent.parent_id.unwrap_or(parent.id))
+}
+
+/// Loads a message the user is allowed to modify: it must belong to this chat,
+/// be authored by the user and not already be deleted.
+async fn find_own_message(
+    state: &AppState,
+    chat_id: i32,
+    user_id: i32,
+    message_id: i32,
+) -> Result<message::Model, &'static str> {
+    let row = message::Entity::find_by_id(message_id)
+        .filter(message::Column::ChatId.eq(chat_id))
+        .filter(message::Column::DeletedAt.is_null())
+        .one(&state.db)
+        .await
+        .map_err(|_| "something went wrong")?
+        .ok_or("message not found")?;
+
+    if row.sender_id != user_id {
+        return Err("you can only modify your own messages");
+    }
+
+    Ok(row)
+}
+
+async fn handle_edit_message(
+    state: &AppState,
+    chat_id: i32,
+    user_id: i32,
+    message_id: i32,
+    content: String,
+) -> Result<(), &'static str> {
+    check_content(&content)?;
+
+    let row = find_own_message(state, chat_id, user_id, message_id).await?;
+    let edited_at = Utc::now().naive_utc();
+
+    let mut active: message::ActiveModel = row.into();
+    active.content = Set(content.clone());
+    active.edited_at = Set(Some(edited_at));
+    active
+        .update(&state.db)
+        .await
+        .map_err(|_| "something went wrong")?;
+
+    update_recent_message(state, chat_id, message_id, |entry| {
+        entry.content = content.clone();
+        entry.edited_at = Some(edited_at);
     })
+    .await;
+
+    publish_event(
+        state,
+        chat_id,
+        &OutgoingMessage::MessageEdited {
+            id: message_id,
+            chat_id,
+            content,
+            edited_at,
+        },
+    )
+    .await;
+
+    Ok(())
 }
 
-async fn handle_socket(
-    socket: WebSocket,
-    state: AppState,
+async fn handle_delete_message(
+    state: &AppState,
     chat_id: i32,
-    username: String,
     user_id: i32,
-) {
-    let (tx, mut rx_ws) = socket.split();
-    let redis_client = state.redis_client.clone();
-    let channel = format!("chat:{chat_id}");
+    message_id: i32,
+) -> Result<(), &'static str> {
+    let row = find_own_message(state, chat_id, user_id, message_id).await?;
+    let parent_id = row.parent_id;
+    let deleted_at = Utc::now().naive_utc();
+
+    let mut active: message::ActiveModel = row.into();
+    active.deleted_at = Set(Some(deleted_at));
+    active
+        .update(&state.db)
+        .await
+        .map_err(|_| "something went wrong")?;
+
+    match parent_id {
+        Some(parent_id) => {
+            update_recent_message(state, chat_id, parent_id, |entry| {
+                entry.reply_count = (entry.reply_count - 1).max(0)
+            })
+            .await
+        }
+        None => remove_recent_message(state, chat_id, message_id).await,
+    }
 
-    let tx = Arc::new(Mutex::new(tx));
-    let tx_redis = tx.clone();
+    publish_event(
+        state,
+        chat_id,
+        &OutgoingMessage::MessageDeleted {
+            id: message_id,
+            chat_id,
+            deleted_at,
+        },
+    )
+    .await;
+
+    Ok(())
+}
 
-    tokio::spawn(async move {
-        let conn = match redis_client.get_async_connection().await {
-            Ok(c) => c,
-            Err(e) => {
-                tracing::error!("pubsub connect failed: {e:?}");
-                return;
-            }
+/// The canonical form of a single Unicode emoji, skin tones included, so
+/// variants such as `❤` and `❤️` count as the same reaction.
+fn reaction_emoji(emoji: &str) -> Option<&'static str> {
+    emojis::get(emoji.trim()).map(|emoji| emoji.as_str())
+}
+
+async fn handle_reaction(
+    state: &AppState,
+    chat_id: i32,
+    user_id: i32,
+    message_id: i32,
+    emoji: String,
+    added: bool,
+) -> Result<(), &'static str> {
+    let emoji = reaction_emoji(&emoji).ok_or("invalid emoji")?;
+
+    let exists = message::Entity::find_by_id(message_id)
+        .filter(message::Column::ChatId.eq(chat_id))
+        .filter(message::Column::DeletedAt.is_null())
+        .count(&state.db)
+        .await
+        .map_err(|_| "something went wrong")?
+        > 0;
+    if !exists {
+        return Err("message not found");
+    }
+
+    if added {
+        let new_reaction = reaction::ActiveModel {
+            message_id: Set(message_id),
+            user_id: Set(user_id),
+            emoji: Set(emoji.to_string()),
+            ..Default::default()
         };
-        let mut pubsub = conn.into_pubsub();
-        if let Err(e) = pubsub.subscribe(&channel).await {
-            tracing::error!("subscribe({channel}) failed: {e:?}");
-            return;
-        }
+        reaction::Entity::insert(new_reaction)
+            .on_conflict(
+                OnConflict::columns([
+                    reaction::Column::MessageId,
+                    reaction::Column::UserId,
+                    reaction::Column::Emoji,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .exec_without_returning(&state.db)
+            .await
+            .map_err(|_| "something went wrong")?;
+    } else {
+        reaction::Entity::delete_many()
+            .filter(reaction::Column::MessageId.eq(message_id))
+            .filter(reaction::Column::UserId.eq(user_id))
+            .filter(reaction::Column::Emoji.eq(emoji))
+            .exec(&state.db)
+            .await
+            .map_err(|_| "something went wrong")?;
+    }
+
+    let reactions = reaction_counts(&state.db, message_id)
+        .await
+        .map_err(|_| "something went wrong")?;
+
+    publish_event(
+        state,
+        chat_id,
+        &OutgoingMessage::ReactionsUpdated {
+            message_id,
+            chat_id,
+            user_id,
+            emoji: emoji.to_string(),
+            added,
+            reactions,
+        },
+    )
+    .await;
+
+    Ok(())
+}
 
-        let mut inbound = pubsub.on_message();
+/// Moves the user's read position in the room forward to `message_id`. Read
+/// positions never move backwards, so stale frames from another tab are ignored.
+async fn handle_mark_read(
+    state: &AppState,
+    chat_id: i32,
+    user_id: i32,
+    username: &str,
+    message_id: i32,
+) -> Result<(), &'static str> {
+    let exists = message::Entity::find_by_id(message_id)
+        .filter(message::Column::ChatId.eq(chat_id))
+        .count(&state.db)
+        .await
+        .map_err(|_| "something went wrong")?
+        > 0;
+    if !exists {
+        return Err("message not found");
+    }
 
-        while let Some(msg) = inbound.next().await {
-            if let Ok(text) = msg.get_payload::<String>() {
-                let mut tx_guard = tx_redis.lock().await;
-                if tx_guard.send(Message::Text(text.into())).await.is_err() {
-                    break;
-                }
-            } else {
-                tracing::error!("invalid payload on {channel}");
-            }
-        }
-    });
+    let current = read_receipt::Entity::find()
+        .filter(read_receipt::Column::ChatId.eq(chat_id))
+        .filter(read_receipt::Column::UserId.eq(user_id))
+        .one(&state.db)
+        .await
+        .map_err(|_| "something went wrong")?;
+    if current.is_some_and(|r| r.last_read_message_id >= message_id) {
+        return Ok(());
+    }
 
-    send_join_notification(&state, chat_id, &username).await;
-    update_user_count(&state, chat_id).await;
-    broadcast_user_list(&state, chat_id).await;
-
-    let redis_client = state.redis_client.clone();
-    let key = format!("chat:{chat_id}");
-    while let Some(Ok(frame)) = rx_ws.next().await {
-        if let Message::Text(text) = frame {
-            match serde_json::from_str::<IncomingMessage>(&text) {
-                Ok(incoming_message) => match incoming_message {
-                    IncomingMessage::ChatMessage { content } => {
-                        let message = message::ActiveModel {
-                            chat_id: Set(chat_id),
-                            sender_id: Set(user_id),
-                            content: Set(content.to_string()),
-                            ..Default::default()
-                        };
-                        let _ = message.insert(&state.db).await;
-                        let redis_messages_key = format!("chat_messages:{chat_id}");
-                        let recent_msg = serde_json::json!({
-                            "username": username,
-                            "content": content
-                        })
-                        .to_string();
-
-                        let _ = redis_client
-                            .lpush(&redis_messages_key, recent_msg)
-                            .await
-                            .unwrap_or(0);
-                        redis_client
-                            .ltrim(&redis_messages_key, 0, 99)
-                            .await
-                            .unwrap_or(());
-
-                        let payload = serde_json::json!({
-                            "type": "message",
-                            "content": format!("{username}: {content}"),
-                        })
-                        .to_string();
-                        let _ = redis_client.publish(&key, payload).await;
-                    }
-                    IncomingMessage::RequestSuggestion { current_input } => {
-                        handle_suggestion_request(state.clone(), chat_id, &current_input, &tx)
-                            .await;
-                    }
-                },
-                Err(e) => {
-                    error!("Unexpected error in handling user messages: {e}")
-                }
+    let receipt = read_receipt::ActiveModel {
+        chat_id: Set(chat_id),
+        user_id: Set(user_id),
+        last_read_message_id: Set(message_id),
+        updated_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+    read_receipt::Entity::insert(receipt)
+        .on_conflict(
+            OnConflict::columns([read_receipt::Column::ChatId, read_receipt::Column::UserId])
+                .value(
+                    read_receipt::Column::LastReadMessageId,
+                    Expr::cust(
+                        "GREATEST(read_receipt.last_read_message_id, \
+                         EXCLUDED.last_read_message_id)",
+                    ),
+                )
+                .update_column(read_receipt::Column::UpdatedAt)
+                .to_owned(),
+        )
+        .exec_without_returning(&state.db)
+        .await
+        .map_err(|_| "something went wrong")?;
+
+    publish_live(
+        state,
+        chat_id,
+        &OutgoingMessage::ReadReceipt {
+            chat_id,
+            user_id,
+            username: username.to_string(),
+            message_id,
+        },
+    )
+    .await;
+    // Keeps the badge in sync on the user's other tabs and devices.
+    push_unread_count(state, chat_id, user_id).await;
+
+    Ok(())
+}
+
+/// Finds a message in the recent-message cache by id, returning the raw entry
+/// so it can be replaced.
+async fn find_recent_message(
+    state: &AppState,
+    chat_id: i32,
+    message_id: i32,
+) -> Option<(String, MessagePayload)> {
+    let raw_messages = state
+        .redis_client
+        .lrange(&format!("chat_messages:{chat_id}"), 0, 99)
+        .await
+        .ok()?;
+
+    raw_messages.into_iter().find_map(|raw| {
+        let entry = serde_json::from_str::<MessagePayload>(&raw).ok()?;
+        (entry.id == message_id).then_some((raw, entry))
+    })
+}
+
+async fn update_recent_message(
+    state: &AppState,
+    chat_id: i32,
+    message_id: i32,
+    update: impl Fn(&mut MessagePayload),
+) {
+    let keys = [format!("chat_messages:{chat_id}")];
+    for _ in 0..MAX_RECENT_MESSAGE_UPDATE_ATTEMPTS {
+        let Some((raw, mut entry)) = find_recent_message(state, chat_id, message_id).await else {
+            return;
+        };
+        update(&mut entry);
+        let Ok(value) = serde_json::to_string(&entry) else {
+            return;
+        };
+
+        match state
+            .redis_client
+            .eval(REPLACE_RECENT_MESSAGE_SCRIPT, &keys, &[raw, value])
+            .await
+        {
+            Ok(0) => continue,
+            Ok(_) => return,
+            Err(e) => {
+                error!("failed to update recent message {message_id}: {e:?}");
+                return;
             }
         }
     }
+    warn!("gave up updating recent message {message_id} after concurrent changes");
 }
 
-async fn send_join_notification(state: &AppState, chat_id: i32, username: &str) {
-    let payload = serde_json::json!({
-        "type": "system_message",
-        "subtype": "join",
-        "content": format!("{username} joined the chat"),
-        "username": username
-    })
-    .to_string();
+async fn remove_recent_message(state: &AppState, chat_id: i32, message_id: i32) {
+    if let Err(e) = state
+        .redis_client
+        .eval(
+            REMOVE_RECENT_MESSAGE_SCRIPT,
+            &[format!("chat_messages:{chat_id}")],
+            &[message_id.to_string()],
+        )
+        .await
+    {
+        error!("failed to remove recent message {message_id}: {e:?}");
+    }
+}
 
-    let key = format!("chat:{chat_id}");
-    let _ = state.redis_client.publish(&key, payload).await;
+/// The Redis stream the room's resumable events are appended to.
+pub(super) fn room_stream_key(chat_id: i32) -> String {
+    format!("chat_events:{chat_id}")
 }
 
-async fn send_leave_notification(state: &AppState, chat_id: i32, username: &str) {
-    let payload = serde_json::json!({
-        "type": "system_message",
-        "subtype": "leave",
-        "content": format!("{username} left the chat"),
-        "username": username
-    })
-    .to_string();
+/// The pub/sub channel for the room's live-only events.
+pub(super) fn room_channel(chat_id: i32) -> String {
+    format!("chat_live:{chat_id}")
+}
 
-    let key = format!("chat:{chat_id}");
-    let _ = state.redis_client.publish(&key, payload).await;
+/// Appends the event to the room's stream, from which every socket in the
+/// room receives it and reconnecting sockets can replay it. Meant for events
+/// that change the room's history: messages, edits, deletions, reactions and
+/// moderation.
+pub(super) async fn publish_event(state: &AppState, chat_id: i32, event: &OutgoingMessage) {
+    let Ok(payload) = serde_json::to_string(event) else {
+        return;
+    };
+    if let Err(e) = state
+        .redis_client
+        .xadd_event(&room_stream_key(chat_id), payload)
+        .await
+    {
+        error!("failed to publish to room {chat_id}: {e:?}");
+    }
 }
 
-async fn update_user_count(state: &AppState, chat_id: i32) {
-    let count = online_user::Entity::find()
-        .filter(online_user::Column::ChatId.eq(chat_id))
-        .count(&state.db)
+/// Sends the event to the sockets currently in the room without recording
+/// it. Meant for presence, typing and read state, which are only of interest
+/// while they are current and are sent afresh when a socket joins.
+pub(super) async fn publish_live(state: &AppState, chat_id: i32, event: &OutgoingMessage) {
+    let Ok(payload) = serde_json::to_string(event) else {
+        return;
+    };
+    if let Err(e) = state
+        .redis_client
+        .publish(&room_channel(chat_id), payload)
         .await
-        .unwrap_or(0);
+    {
+        error!("failed to publish to room {chat_id}: {e:?}");
+    }
+}
 
-    let payload = serde_json::json!({
-        "type": "user_count",
-        "chatId": chat_id,
-        "content": count,
-    })
-    .to_string();
+pub(super) async fn send_event(tx: &SocketSink, event: &OutgoingMessage) {
+    if let Ok(json) = serde_json::to_string(event) {
+        let _ = tx.lock().await.send(Message::Text(json.into())).await;
+    }
+}
+
+pub(super) async fn send_error(tx: &SocketSink, chat_id: Option<i32>, error: &str) {
+    let response = OutgoingMessage::Error {
+        chat_id,
+        error: error.to_string(),
+    };
+    send_event(tx, &response).await;
+}
+
+pub(super) async fn send_join_notification(
+    state: &AppState,
+    chat_id: i32,
+    user_id: i32,
+    username: &str,
+) {
+    if is_invisible(state, user_id).await {
+        return;
+    }
+    let event = OutgoingMessage::SystemMessage {
+        subtype: SystemMessageKind::Join,
+        chat_id,
+        user_id,
+        username: username.to_string(),
+        content: format!("{username} joined the chat"),
+        created_at: Utc::now().naive_utc(),
+    };
+    publish_live(state, chat_id, &event).await;
+}
 
-    let _ = state.redis_client.publish("chat_list", payload).await;
+pub(super) async fn send_leave_notification(
+    state: &AppState,
+    chat_id: i32,
+    user_id: i32,
+    username: &str,
+) {
+    if is_invisible(state, user_id).await {
+        return;
+    }
+    let event = OutgoingMessage::SystemMessage {
+        subtype: SystemMessageKind::Leave,
+        chat_id,
+        user_id,
+        username: username.to_string(),
+        content: format!("{username} left the chat"),
+        created_at: Utc::now().naive_utc(),
+    };
+    publish_live(state, chat_id, &event).await;
 }
 
-async fn broadcast_user_list(state: &AppState, chat_id: i32) {
-    let rows = online_user::Entity::find()
-        .filter(online_user::Column::ChatId.eq(chat_id))
-        .find_also_related(user::Entity)
-        .all(&state.db)
+/// Invisible users come and go without announcements.
+async fn is_invisible(state: &AppState, user_id: i32) -> bool {
+    user::Entity::find_by_id(user_id)
+        .one(&state.db)
         .await
-        .unwrap_or_default();
+        .ok()
+        .flatten()
+        .is_some_and(|u| u.status == UserStatus::Invisible)
+}
 
-    let names: Vec<String> = rows
-        .into_iter()
-        .filter_map(|(_online, maybe_user)| maybe_user)
-        .map(|u| u.username)
-        .collect();
+pub(super) async fn update_user_count(state: &AppState, chat_id: i32) {
+    let count = live_user_ids(state, chat_id)
+        .await
+        .map_or(0, |ids| ids.len() as u64);
+
+    let event = OutgoingMessage::UserCount {
+        chat_id,
+        content: count,
+    };
+    if let Ok(payload) = serde_json::to_string(&event) {
+        let _ = state.redis_client.publish("chat_list", payload).await;
+    }
+}
 
-    let payload = serde_json::json!({
-        "type": "user_list",
-        "content": names
-    })
-    .to_string();
+pub(super) async fn broadcast_user_list(state: &AppState, chat_id: i32) {
+    publish_live(state, chat_id, &room_user_list(state, chat_id).await).await;
+}
 
-    let _ = state
-        .redis_client
-        .publish(&format!("chat:{chat_id}"), payload)
-        .await;
+/// The `user_list` of the users present in the room.
+pub(super) async fn room_user_list(state: &AppState, chat_id: i32) -> OutgoingMessage {
+    let user_ids = live_user_ids(state, chat_id).await.unwrap_or_default();
+    let users: Vec<OnlineUserEntry> = if user_ids.is_empty() {
+        Vec::new()
+    } else {
+        user::Entity::find()
+            .filter(user::Column::Id.is_in(user_ids))
+            .order_by_asc(user::Column::Id)
+            .all(&state.db)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|u| u.status != UserStatus::Invisible)
+            .map(|u| {
+                let (status, status_message) = visible_status(&u, true);
+                OnlineUserEntry {
+                    id: u.id,
+                    username: u.username,
+                    display_name: u.display_name,
+                    avatar_url: u.avatar_url,
+                    status,
+                    status_message,
+                    last_seen_at: u.last_seen_at,
+                }
+            })
+            .collect()
+    };
+
+    OutgoingMessage::UserList { chat_id, users }
 }
 
 async fn handle_suggestion_request(
     state: AppState,
     chat_id: i32,
     current_input: &str,
-    tx: &Arc<Mutex<SplitSink<WebSocket, Message>>>,
+    tx: &SocketSink,
 ) {
     let raw_messages: Vec<String> = state
         .redis_client
@@ -246,7 +767,7 @@ async fn handle_suggestion_request(
 
     let mut context: Vec<ChatMessage> = raw_messages
         .into_iter()
-        .filter_map(|s| serde_json::from_str::<RecentEntry>(&s).ok())
+        .filter_map(|s| serde_json::from_str::<MessagePayload>(&s).ok())
         .rev()
         .map(|e| ChatMessage {
             role: "user".to_string(),
@@ -259,22 +780,80 @@ async fn handle_suggestion_request(
         content: current_input.to_string(),
     });
 
-    let mut tx_guard = tx.lock().await;
+    let response = match state.ollama_client.get_chat_suggestion(context).await {
+        Ok(suggestion) => OutgoingMessage::Suggestion {
+            chat_id,
+            text: suggestion,
+        },
+        Err(_) => OutgoingMessage::SuggestionError {
+            chat_id,
+            error: "Suggestion unavailable".to_string(),
+        },
+    };
+    send_event(tx, &response).await;
+}
 
-    match state.ollama_client.get_chat_suggestion(context).await {
-        Ok(suggestion) => {
-            let response = OutgoingMessage::Suggestion { text: suggestion };
-            if let Ok(json) = serde_json::to_string(&response) {
-                let _ = tx_guard.send(Message::Text(json.into())).await;
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_stream_event_ids() {
+        assert!(is_event_id("1700000000000-0"));
+        assert!(is_event_id("0-0"));
+        assert!(is_event_id(&format!("{}-{}", u64::MAX, u64::MAX)));
+    }
+
+    #[test]
+    fn rejects_anything_else() {
+        for id in [
+            "",
+            "-",
+            "1700000000000",
+            "1700000000000-",
+            "-0",
+            "1-2-3",
+            "1-*",
+            "$",
+            "+",
+            "+1-0",
+            "1-+0",
+            "-1-0",
+            " 1-0",
+            "1-0 ",
+            "abc-0",
+            "١-٠",
+        ] {
+            assert!(!is_event_id(id), "{id:?}");
         }
-        Err(_) => {
-            let response = OutgoingMessage::SuggestionError {
-                error: "Suggestion unavailable".to_string(),
-            };
-            if let Ok(json) = serde_json::to_string(&response) {
-                let _ = tx_guard.send(Message::Text(json.into())).await;
-            }
+        assert!(!is_event_id(&format!("{}0-0", u64::MAX)));
+    }
+
+    #[test]
+    fn message_content_must_be_present_and_bounded() {
+        assert_eq!(check_content(" \n\t "), Err("message cannot be empty"));
+        assert_eq!(check_content("hi"), Ok(()));
+        let longest = "é".repeat(MAX_MESSAGE_CHARS);
+        assert_eq!(check_content(&longest), Ok(()));
+        assert_eq!(
+            check_content(&format!("{longest}é")),
+            Err("message is too long")
+        );
+    }
+
+    #[test]
+    fn reactions_are_single_emojis() {
+        for emoji in ["👍", "👍🏽", "❤️", "👨‍👩‍👧", "🇫🇷", " 🎉 "] {
+            assert!(reaction_emoji(emoji).is_some(), "{emoji:?}");
+        }
+        for emoji in ["", " ", "lol", "a", "👍👍", "👍 lol", "<b>", "\u{200d}"] {
+            assert_eq!(reaction_emoji(emoji), None, "{emoji:?}");
         }
     }
+
+    #[test]
+    fn reaction_variants_are_stored_the_same() {
+        assert_eq!(reaction_emoji("❤"), reaction_emoji("❤️"));
+        assert_ne!(reaction_emoji("👍"), reaction_emoji("👍🏽"));
+    }
 }
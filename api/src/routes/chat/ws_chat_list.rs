@@ -1,5 +1,3 @@
-use std::sync::Arc;
-
 use axum::{
     extract::{
         State, WebSocketUpgrade,
@@ -8,6 +6,7 @@ use axum::{
     response::IntoResponse,
 };
 use futures::{SinkExt, StreamExt};
+use tracing::error;
 
 use crate::{AppState, clients::RedisClient};
 
@@ -16,44 +15,29 @@ pub async fn chat_list_ws(
     ws: WebSocketUpgrade,
 ) -> impl IntoResponse {
     ws.on_upgrade(move |socket| async move {
-        handle_chat_list_socket(socket, state.redis_client).await;
+        handle_chat_list_socket(socket, &state.redis_client).await;
     })
 }
 
-async fn handle_chat_list_socket(socket: WebSocket, redis_client: Arc<RedisClient>) {
-    let (mut tx, mut rx_ws) = socket.split();
-    let channel = "chat_list".to_string();
-
-    tokio::spawn(async move {
-        let conn = match redis_client.get_async_connection().await {
-            Ok(c) => c,
-            Err(e) => {
-                tracing::error!("pubsub connect failed: {:?}", e);
-                return;
-            }
-        };
-
-        let mut pubsub = conn.into_pubsub();
-        if let Err(e) = pubsub.subscribe(&channel).await {
-            tracing::error!("subscribe({}) failed: {:?}", channel, e);
+async fn handle_chat_list_socket(socket: WebSocket, redis_client: &RedisClient) {
+    let mut subscription = match redis_client.subscribe("chat_list").await {
+        Ok(subscription) => subscription,
+        Err(e) => {
+            error!("failed to subscribe to the chat list: {e:?}");
             return;
         }
+    };
+    let (mut tx, mut rx_ws) = socket.split();
 
-        let mut inbound = pubsub.on_message();
-
-        while let Some(msg) = inbound.next().await {
-            match msg.get_payload::<String>() {
-                Ok(text) => {
-                    if tx.send(Message::Text(text.into())).await.is_err() {
-                        break;
-                    }
-                }
-                Err(e) => {
-                    tracing::error!("invalid payload on {}: {:?}", channel, e);
-                }
+    let forwarder = tokio::spawn(async move {
+        while let Some(text) = subscription.recv().await {
+            if tx.send(Message::Text(text.into())).await.is_err() {
+                break;
             }
         }
     });
 
     while let Some(Ok(_frame)) = rx_ws.next().await {}
+
+    forwarder.abort();
 }
@@ -0,0 +1,540 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum::extract::ws::{Message, WebSocket};
+use futures::{SinkExt, StreamExt, lock::Mutex, stream::SplitSink};
+use tokio::{
+    sync::mpsc,
+    task::JoinHandle,
+    time::{Instant, interval_at, sleep_until},
+};
+use tracing::{error, warn};
+
+use super::{
+    access::find_accessible_chat,
+    presence::{
+        HEARTBEAT_INTERVAL, mark_absent, mark_present, next_connection_id, touch_last_seen,
+    },
+    ws_chat::{
+        broadcast_user_list, handle_incoming, is_event_id, publish_live, room_channel,
+        room_stream_key, room_user_list, send_error, send_event, send_join_notification,
+        send_leave_notification, update_user_count,
+    },
+};
+
+use crate::{
+    AppState,
+    clients::Lagged,
+    entity::chat,
+    errors::Error,
+    models::{
+        claims::Claims,
+        messages::{
+            IncomingMessage, MultiplexedMessage, OutgoingMessage, RoomControlEvent,
+            RoomScopedMessage, SubscriptionFrame, UserControlEvent,
+        },
+    },
+};
+
+pub(super) type SocketSink = Arc<Mutex<SplitSink<WebSocket, Message>>>;
+
+const MAX_SUBSCRIPTIONS: usize = 50;
+const TYPING_THROTTLE: Duration = Duration::from_secs(2);
+const TYPING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How a socket addresses rooms.
+pub(super) enum SessionMode {
+    /// `/ws/chat?chat_id=`: bound to one room, frames carry no `chat_id`, and
+    /// the socket is closed when the user is removed from the room. Events
+    /// after the given `last_event_id` are replayed first.
+    Single(chat::Model, Option<String>),
+    /// `/ws/rooms`: rooms come and go with `subscribe`/`unsubscribe` frames and
+    /// every room frame names its `chat_id`.
+    Multiplexed,
+}
+
+/// A room this socket is present in.
+struct Room {
+    is_listed: bool,
+    subscriber: JoinHandle<()>,
+    typing: TypingState,
+}
+
+struct Session {
+    state: AppState,
+    user_id: i32,
+    username: String,
+    /// Tells this socket's presence apart from the user's other tabs.
+    connection_id: i64,
+    tx: SocketSink,
+    rooms: HashMap<i32, Room>,
+    removed_tx: mpsc::UnboundedSender<i32>,
+}
+
+pub(super) async fn run_session(
+    socket: WebSocket,
+    state: AppState,
+    claims: Claims,
+    mode: SessionMode,
+) {
+    let Claims {
+        sub: user_id,
+        username,
+        jti: session_id,
+        ..
+    } = claims;
+
+    let connection_id = match next_connection_id(&state).await {
+        Ok(connection_id) => connection_id,
+        Err(e) => {
+            error!("failed to allocate a connection id: {e:?}");
+            return;
+        }
+    };
+
+    // Subscribed first so a revocation while joining rooms is not missed.
+    let mut user_events = match state
+        .redis_client
+        .subscribe(&format!("user:{user_id}"))
+        .await
+    {
+        Ok(subscription) => subscription,
+        Err(e) => {
+            error!("failed to subscribe to the events of user {user_id}: {e:?}");
+            return;
+        }
+    };
+
+    let (tx, mut rx_ws) = socket.split();
+    let (removed_tx, mut removed_rx) = mpsc::unbounded_channel();
+    let mut session = Session {
+        state,
+        user_id,
+        username,
+        connection_id,
+        tx: Arc::new(Mutex::new(tx)),
+        rooms: HashMap::new(),
+        removed_tx,
+    };
+
+    let bound_chat = match mode {
+        SessionMode::Single(chat_row, last_event_id) => {
+            if !session.join(&chat_row, last_event_id).await {
+                return;
+            }
+            Some(chat_row.id)
+        }
+        SessionMode::Multiplexed => None,
+    };
+
+    let mut heartbeat = interval_at(Instant::now() + HEARTBEAT_INTERVAL, HEARTBEAT_INTERVAL);
+
+    loop {
+        let typing_deadline = session.next_typing_deadline();
+
+        tokio::select! {
+            frame = rx_ws.next() => {
+                let Some(Ok(frame)) = frame else { break };
+                if let Message::Text(text) = frame {
+                    session.handle_frame(bound_chat, &text).await;
+                }
+            }
+            _ = sleep_until(typing_deadline.unwrap_or_else(Instant::now)), if typing_deadline.is_some() => {
+                session.expire_typing().await;
+            }
+            _ = heartbeat.tick() => {
+                session.heartbeat().await;
+            }
+            // The user signed this session out, possibly on another instance.
+            Some(text) = user_events.recv() => {
+                if UserControlEvent::revokes(&text, &session_id) {
+                    let _ = session.tx.lock().await.send(Message::Close(None)).await;
+                    break;
+                }
+            }
+            // A moderator kicked or banned this user, possibly from another instance.
+            Some(chat_id) = removed_rx.recv() => {
+                if bound_chat.is_some() {
+                    let _ = session.tx.lock().await.send(Message::Close(None)).await;
+                    break;
+                }
+                session.leave(chat_id).await;
+            }
+        }
+    }
+
+    session.leave_all().await;
+    if let Err(e) = touch_last_seen(&session.state, &[session.user_id]).await {
+        error!("failed to record last_seen_at for user {user_id}: {e:?}");
+    }
+}
+
+impl Session {
+    async fn handle_frame(&mut self, bound_chat: Option<i32>, text: &str) {
+        if let Some(chat_id) = bound_chat {
+            match serde_json::from_str::<IncomingMessage>(text) {
+                Ok(message) => self.dispatch(chat_id, message).await,
+                Err(e) => error!("Unexpected error in handling user messages: {e}"),
+            }
+            return;
+        }
+
+        match serde_json::from_str::<MultiplexedMessage>(text) {
+            Ok(MultiplexedMessage::Subscription(SubscriptionFrame::Subscribe {
+                chat_id,
+                last_event_id,
+            })) => {
+                self.subscribe(chat_id, last_event_id).await;
+            }
+            Ok(MultiplexedMessage::Subscription(SubscriptionFrame::Unsubscribe { chat_id })) => {
+                if self.leave(chat_id).await {
+                    send_event(&self.tx, &OutgoingMessage::Unsubscribed { chat_id }).await;
+                }
+            }
+            Ok(MultiplexedMessage::Room(RoomScopedMessage { chat_id, message })) => {
+                self.dispatch(chat_id, message).await;
+            }
+            Err(e) => error!("Unexpected error in handling user messages: {e}"),
+        }
+    }
+
+    async fn dispatch(&mut self, chat_id: i32, message: IncomingMessage) {
+        let Some(room) = self.rooms.get_mut(&chat_id) else {
+            send_error(&self.tx, Some(chat_id), "not subscribed to this room").await;
+            return;
+        };
+
+        handle_incoming(
+            &self.state,
+            chat_id,
+            &self.username,
+            self.user_id,
+            &self.tx,
+            &mut room.typing,
+            message,
+        )
+        .await;
+    }
+
+    async fn subscribe(&mut self, chat_id: i32, last_event_id: Option<String>) {
+        if !self.rooms.contains_key(&chat_id) {
+            if self.rooms.len() >= MAX_SUBSCRIPTIONS {
+                send_error(&self.tx, Some(chat_id), "too many subscriptions").await;
+                return;
+            }
+            if last_event_id.as_deref().is_some_and(|id| !is_event_id(id)) {
+                send_error(&self.tx, Some(chat_id), "invalid last_event_id").await;
+                return;
+            }
+
+            let chat_row = match find_accessible_chat(&self.state.db, chat_id, self.user_id).await {
+                Ok(chat_row) => chat_row,
+                Err(e) => {
+                    let reason = match e {
+                        Error::NotFound => "chat not found",
+                        Error::Forbidden => "forbidden",
+                        _ => "something went wrong",
+                    };
+                    send_error(&self.tx, Some(chat_id), reason).await;
+                    return;
+                }
+            };
+            if !self.join(&chat_row, last_event_id).await {
+                send_error(&self.tx, Some(chat_id), "something went wrong").await;
+                return;
+            }
+        }
+
+        send_event(&self.tx, &OutgoingMessage::Subscribed { chat_id }).await;
+    }
+
+    /// Starts forwarding the room's events, marks the connection present and
+    /// announces the arrival unless another tab of the user is already there.
+    /// Returns `false` if the room's stream could not be read.
+    async fn join(&mut self, chat_row: &chat::Model, last_event_id: Option<String>) -> bool {
+        let chat_id = chat_row.id;
+        // Direct conversations and private rooms keep their presence off the
+        // public room list feed.
+        let is_listed = !chat_row.is_direct && !chat_row.is_private;
+
+        let subscriber = match spawn_room_subscriber(
+            &self.state,
+            chat_id,
+            self.user_id,
+            last_event_id,
+            self.tx.clone(),
+            self.removed_tx.clone(),
+        )
+        .await
+        {
+            Ok(subscriber) => subscriber,
+            Err(e) => {
+                error!("failed to subscribe to room {chat_id}: {e:?}");
+                return false;
+            }
+        };
+
+        self.rooms.insert(
+            chat_id,
+            Room {
+                is_listed,
+                subscriber,
+                typing: TypingState::default(),
+            },
+        );
+
+        if self.refresh_presence(chat_id).await {
+            broadcast_user_list(&self.state, chat_id).await;
+        }
+        // The live channel may not be subscribed yet, so this socket gets the
+        // list directly.
+        send_event(&self.tx, &room_user_list(&self.state, chat_id).await).await;
+        true
+    }
+
+    /// Marks the connection present in the room and announces the user if
+    /// they were not there before. Returns whether they were announced.
+    async fn refresh_presence(&self, chat_id: i32) -> bool {
+        let is_listed = self.rooms.get(&chat_id).is_some_and(|room| room.is_listed);
+
+        match mark_present(&self.state, chat_id, self.user_id, self.connection_id).await {
+            Ok(true) => {
+                send_join_notification(&self.state, chat_id, self.user_id, &self.username).await;
+                if is_listed {
+                    update_user_count(&self.state, chat_id).await;
+                }
+                true
+            }
+            Ok(false) => false,
+            Err(e) => {
+                error!("failed to mark presence in room {chat_id}: {e:?}");
+                false
+            }
+        }
+    }
+
+    /// Keeps the connection's presence entries from expiring. An entry the
+    /// reaper removed in the meantime, e.g. while Redis was unreachable, is
+    /// put back and the user announced again.
+    async fn heartbeat(&self) {
+        let chat_ids: Vec<i32> = self.rooms.keys().copied().collect();
+        for chat_id in chat_ids {
+            if self.refresh_presence(chat_id).await {
+                broadcast_user_list(&self.state, chat_id).await;
+            }
+        }
+    }
+
+    /// Undoes `join`. Returns whether the socket was in the room at all.
+    async fn leave(&mut self, chat_id: i32) -> bool {
+        let Some(mut room) = self.rooms.remove(&chat_id) else {
+            return false;
+        };
+
+        room.typing
+            .stop(&self.state, chat_id, self.user_id, &self.username)
+            .await;
+        room.subscriber.abort();
+
+        // The user is still present through another tab, or the reaper got to
+        // the entry first and announced the departure itself.
+        let departed = mark_absent(&self.state, chat_id, self.user_id, self.connection_id)
+            .await
+            .unwrap_or_else(|e| {
+                error!("failed to clear presence in room {chat_id}: {e:?}");
+                false
+            });
+        if departed {
+            send_leave_notification(&self.state, chat_id, self.user_id, &self.username).await;
+            if room.is_listed {
+                update_user_count(&self.state, chat_id).await;
+            }
+            broadcast_user_list(&self.state, chat_id).await;
+        }
+        true
+    }
+
+    async fn leave_all(&mut self) {
+        let chat_ids: Vec<i32> = self.rooms.keys().copied().collect();
+        for chat_id in chat_ids {
+            self.leave(chat_id).await;
+        }
+    }
+
+    fn next_typing_deadline(&self) -> Option<Instant> {
+        self.rooms
+            .values()
+            .filter_map(|room| room.typing.expires_at)
+            .min()
+    }
+
+    async fn expire_typing(&mut self) {
+        let now = Instant::now();
+        for (&chat_id, room) in self.rooms.iter_mut() {
+            if room.typing.expires_at.is_some_and(|at| at <= now) {
+                room.typing
+                    .stop(&self.state, chat_id, self.user_id, &self.username)
+                    .await;
+            }
+        }
+    }
+}
+
+/// Forwards the room's event stream and live channel to the socket, first
+/// replaying whatever was streamed after `last_event_id`. If some of that was
+/// already trimmed, or the socket later falls behind the stream, a `resync`
+/// frame tells the client to reload the room instead. When the user is
+/// removed from the room the event is delivered, the session is told through
+/// `removed_tx`, and forwarding stops.
+async fn spawn_room_subscriber(
+    state: &AppState,
+    chat_id: i32,
+    user_id: i32,
+    last_event_id: Option<String>,
+    tx: SocketSink,
+    removed_tx: mpsc::UnboundedSender<i32>,
+) -> Result<JoinHandle<()>, Error> {
+    let key = room_stream_key(chat_id);
+    // Subscribing before the task starts means nothing published after
+    // `join` returns is missed.
+    let (mut subscription, live_from) = state.redis_client.subscribe_stream(&key).await?;
+    let mut live = state.redis_client.subscribe(&room_channel(chat_id)).await?;
+    let (missed, is_gap) = match last_event_id {
+        Some(after)
+            if state
+                .redis_client
+                .stream_trimmed_after(&key, &after)
+                .await? =>
+        {
+            (Vec::new(), true)
+        }
+        Some(after) => {
+            let missed = state
+                .redis_client
+                .stream_range(&key, &after, &live_from)
+                .await?;
+            (missed, false)
+        }
+        None => (Vec::new(), false),
+    };
+
+    Ok(tokio::spawn(async move {
+        if is_gap {
+            send_event(&tx, &OutgoingMessage::Resync { chat_id }).await;
+        }
+        // Old removals are history by now and the stream holds no typing
+        // indicators, so replayed events are forwarded as they are.
+        for text in missed {
+            if tx
+                .lock()
+                .await
+                .send(Message::Text(text.into()))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+
+        loop {
+            let text = tokio::select! {
+                event = subscription.recv_checked() => match event {
+                    Some(Ok(text)) => Some(text),
+                    Some(Err(Lagged(skipped))) => {
+                        warn!("socket in room {chat_id} fell {skipped} events behind");
+                        send_event(&tx, &OutgoingMessage::Resync { chat_id }).await;
+                        continue;
+                    }
+                    None => None,
+                },
+                text = live.recv() => text,
+            };
+            let Some(text) = text else { break };
+            if is_own_typing(&text, user_id) {
+                continue;
+            }
+            let is_removed = matches!(
+                serde_json::from_str::<RoomControlEvent>(&text),
+                Ok(RoomControlEvent::MemberRemoved { user_id: target }) if target == user_id
+            );
+
+            let mut tx_guard = tx.lock().await;
+            if tx_guard.send(Message::Text(text.into())).await.is_err() {
+                break;
+            }
+
+            if is_removed {
+                let _ = removed_tx.send(chat_id);
+                break;
+            }
+        }
+    }))
+}
+
+/// Typing indicators are not echoed back to the typist.
+fn is_own_typing(text: &str, user_id: i32) -> bool {
+    matches!(
+        serde_json::from_str::<RoomControlEvent>(text),
+        Ok(RoomControlEvent::UserTyping { user_id: typist }) if typist == user_id
+    )
+}
+
+/// Per-room typing indicator: publishes `user_typing` at most once per
+/// `TYPING_THROTTLE` and a stop event once `TYPING_TIMEOUT` passes without a
+/// new typing frame.
+#[derive(Default)]
+pub(super) struct TypingState {
+    last_published: Option<Instant>,
+    expires_at: Option<Instant>,
+}
+
+impl TypingState {
+    pub(super) async fn refresh(
+        &mut self,
+        state: &AppState,
+        chat_id: i32,
+        user_id: i32,
+        username: &str,
+    ) {
+        let now = Instant::now();
+        self.expires_at = Some(now + TYPING_TIMEOUT);
+
+        if self
+            .last_published
+            .is_some_and(|at| now.duration_since(at) < TYPING_THROTTLE)
+        {
+            return;
+        }
+        self.last_published = Some(now);
+        publish_typing(state, chat_id, user_id, username, true).await;
+    }
+
+    pub(super) async fn stop(
+        &mut self,
+        state: &AppState,
+        chat_id: i32,
+        user_id: i32,
+        username: &str,
+    ) {
+        if self.expires_at.take().is_none() {
+            return;
+        }
+        self.last_published = None;
+        publish_typing(state, chat_id, user_id, username, false).await;
+    }
+}
+
+async fn publish_typing(
+    state: &AppState,
+    chat_id: i32,
+    user_id: i32,
+    username: &str,
+    typing: bool,
+) {
+    let event = OutgoingMessage::UserTyping {
+        chat_id,
+        user_id,
+        username: username.to_string(),
+        typing,
+    };
+    publish_live(state, chat_id, &event).await;
+}
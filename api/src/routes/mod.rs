@@ -1,26 +1,79 @@
 use crate::AppState;
 use axum::{
     Router,
-    routing::{get, post},
+    routing::{get, patch, post},
 };
 
 mod auth;
+mod blobs;
 mod chat;
 mod monitoring;
+mod notifications;
+mod users;
+
+pub use chat::spawn_presence_reaper;
 
 pub fn public_router() -> Router<AppState> {
     Router::new()
         .route("/register", post(auth::register))
+        .route("/username-available", get(auth::username_available))
         .route("/login", post(auth::login))
+        .route("/refresh", post(auth::refresh))
+        .route("/password/forgot", post(auth::forgot_password))
+        .route("/password/reset", post(auth::reset_password))
+        .route("/blobs/{*key}", get(blobs::get_blob))
 }
 
 pub fn protected_router() -> Router<AppState> {
     Router::new()
         .route("/logout", post(auth::logout))
+        .route("/logout/all", post(auth::logout_everywhere))
+        .route("/sessions", get(auth::list_sessions))
         .route("/chat", post(chat::create_chat))
         .route("/chat", get(chat::active_chats))
         .route("/chat/{id}", get(chat::get_chat))
-        .route("/chat/name/{name}", get(chat::get_all_chats_by_name))
+        .route("/chat/{id}/messages", get(chat::get_messages))
+        .route(
+            "/chat/{id}/messages/{message_id}/thread",
+            get(chat::get_thread),
+        )
+        .route("/chat/search", get(chat::search_chats))
+        .route("/chat/{id}/members", get(chat::list_members))
+        .route("/chat/{id}/members", post(chat::invite_member))
+        .route("/chat/{id}/members/accept", post(chat::accept_invitation))
+        .route("/chat/{id}/members/leave", post(chat::leave_chat))
+        .route(
+            "/chat/{id}/members/{user_id}/role",
+            post(chat::set_member_role),
+        )
+        .route("/chat/{id}/moderation/kick", post(chat::kick_member))
+        .route("/chat/{id}/moderation/ban", post(chat::ban_member))
+        .route("/chat/{id}/moderation/unban", post(chat::unban_member))
+        .route("/chat/{id}/moderation/mute", post(chat::mute_member))
+        .route("/chat/{id}/moderation/unmute", post(chat::unmute_member))
+        .route("/chat/{id}/moderation/log", get(chat::moderation_log))
+        .route("/invitations", get(chat::list_invitations))
+        .route("/dm", post(chat::open_direct_chat))
+        .route("/dm", get(chat::list_direct_chats))
+        .route("/search", get(chat::search_messages))
+        .route("/notifications", get(notifications::list_notifications))
+        .route(
+            "/notifications/read",
+            post(notifications::mark_all_notifications_read),
+        )
+        .route(
+            "/notifications/{id}/read",
+            post(notifications::mark_notification_read),
+        )
+        .route("/users/{id}", get(users::get_user))
+        .route("/me/status", post(users::set_status))
+        .route("/me/password", post(auth::change_password))
+        .route("/me/profile", get(users::get_my_profile))
+        .route("/me/profile", patch(users::update_my_profile))
+        .route(
+            "/me/avatar",
+            post(users::upload_avatar).layer(users::avatar_body_limit()),
+        )
         .route("/whoami", get(auth::whoami))
 }
 
@@ -28,8 +81,14 @@ pub fn health_router() -> Router<AppState> {
     Router::new().route("/health", get(monitoring::health))
 }
 
+pub fn well_known_router() -> Router<AppState> {
+    Router::new().route("/.well-known/jwks.json", get(auth::jwks))
+}
+
 pub fn ws_router() -> Router<AppState> {
     Router::new()
         .route("/chat", get(chat::chat_ws))
+        .route("/rooms", get(chat::rooms_ws))
         .route("/chat-list", get(chat::chat_list_ws))
+        .route("/me", get(notifications::user_ws))
 }
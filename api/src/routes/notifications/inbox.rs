@@ -0,0 +1,92 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use chrono::Utc;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect, sea_query::Expr};
+
+use crate::{
+    AppState,
+    entity::notification,
+    errors::Error,
+    models::{
+        claims::Claims,
+        notification::{NotificationPayload, NotificationQuery},
+    },
+};
+
+use super::publish::notification_payloads;
+
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 100;
+
+pub async fn list_notifications(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Query(params): Query<NotificationQuery>,
+) -> Result<(StatusCode, Json<Vec<NotificationPayload>>), Error> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let mut query = notification_payloads().filter(notification::Column::UserId.eq(claims.sub));
+    if params.unread {
+        query = query.filter(notification::Column::ReadAt.is_null());
+    }
+    if let Some(before) = params.before {
+        query = query.filter(notification::Column::Id.lt(before));
+    }
+
+    let notifications = query
+        .order_by_desc(notification::Column::Id)
+        .limit(limit)
+        .into_model::<NotificationPayload>()
+        .all(&state.db)
+        .await?;
+
+    Ok((StatusCode::OK, Json(notifications)))
+}
+
+pub async fn mark_notification_read(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<StatusCode, Error> {
+    let row = notification::Entity::find_by_id(id)
+        .filter(notification::Column::UserId.eq(claims.sub))
+        .one(&state.db)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    if row.read_at.is_none() {
+        notification::Entity::update_many()
+            .col_expr(
+                notification::Column::ReadAt,
+                Expr::value(Utc::now().naive_utc()),
+            )
+            .filter(notification::Column::Id.eq(id))
+            .exec(&state.db)
+            .await?;
+    }
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn mark_all_notifications_read(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, Error> {
+    notification::Entity::update_many()
+        .col_expr(
+            notification::Column::ReadAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .filter(notification::Column::UserId.eq(claims.sub))
+        .filter(notification::Column::ReadAt.is_null())
+        .exec(&state.db)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,7 @@
+mod inbox;
+mod publish;
+mod ws_user;
+
+pub use inbox::{list_notifications, mark_all_notifications_read, mark_notification_read};
+pub use publish::{load_notification, publish_to_user};
+pub use ws_user::user_ws;
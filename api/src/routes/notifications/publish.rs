@@ -0,0 +1,66 @@
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, JoinType, QueryFilter, QuerySelect,
+    RelationTrait, Select,
+    sea_query::{Alias, Expr},
+};
+use tracing::error;
+
+use crate::{
+    AppState,
+    entity::{chat, message, notification, user},
+    models::{messages::OutgoingMessage, notification::NotificationPayload},
+};
+
+/// Pushes an event to every socket the user has open on the `user:{id}`
+/// channel, whichever instance it is connected to.
+pub async fn publish_to_user(state: &AppState, user_id: i32, event: &OutgoingMessage) {
+    let Ok(payload) = serde_json::to_string(event) else {
+        return;
+    };
+    if let Err(e) = state
+        .redis_client
+        .publish(&format!("user:{user_id}"), payload)
+        .await
+    {
+        error!("failed to publish to user {user_id}: {e:?}");
+    }
+}
+
+/// Selects notifications joined with their room, message and actor, shaped as
+/// `NotificationPayload` rows.
+pub(super) fn notification_payloads() -> Select<notification::Entity> {
+    notification::Entity::find()
+        .join(JoinType::InnerJoin, notification::Relation::Chat.def())
+        .join(JoinType::InnerJoin, notification::Relation::Message.def())
+        .join_as(
+            JoinType::LeftJoin,
+            notification::Relation::Actor.def(),
+            Alias::new("actor"),
+        )
+        .select_only()
+        .column(notification::Column::Id)
+        .column(notification::Column::Kind)
+        .column(notification::Column::ChatId)
+        .column_as(chat::Column::Name, "chat_name")
+        .column(notification::Column::MessageId)
+        .column(notification::Column::ActorId)
+        .column_as(
+            Expr::col((Alias::new("actor"), user::Column::Username)),
+            "actor_username",
+        )
+        .column_as(message::Column::Content, "content")
+        .column(notification::Column::CreatedAt)
+        .column(notification::Column::ReadAt)
+        .filter(message::Column::DeletedAt.is_null())
+}
+
+pub async fn load_notification(
+    db: &DatabaseConnection,
+    id: i32,
+) -> Result<Option<NotificationPayload>, DbErr> {
+    notification_payloads()
+        .filter(notification::Column::Id.eq(id))
+        .into_model::<NotificationPayload>()
+        .one(db)
+        .await
+}
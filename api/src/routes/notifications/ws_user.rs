@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use axum::{
+    Extension,
+    extract::{
+        State, WebSocketUpgrade,
+        ws::{Message, WebSocket},
+    },
+    response::IntoResponse,
+};
+use futures::{SinkExt, StreamExt, lock::Mutex, stream::SplitSink};
+use tokio::time::{Instant, interval_at};
+use tracing::error;
+
+use crate::{
+    AppState,
+    errors::Error,
+    models::{
+        claims::Claims,
+        messages::{OutgoingMessage, UserControlEvent, UserFrame},
+    },
+    routes::{
+        chat::{HEARTBEAT_INTERVAL, clear_user_socket, mark_user_socket, next_connection_id},
+        users::update_status,
+    },
+};
+
+type UserSink = Arc<Mutex<SplitSink<WebSocket, Message>>>;
+
+/// Streams everything published on the caller's `user:{id}` channel: direct
+/// messages, mentions, invitations, removals, unread-count and status
+/// changes. Accepts `set_status` frames.
+pub async fn user_ws(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| async move {
+        handle_user_socket(socket, state, claims.sub, claims.jti).await;
+    })
+}
+
+async fn handle_user_socket(socket: WebSocket, state: AppState, user_id: i32, session_id: String) {
+    let mut subscription = match state
+        .redis_client
+        .subscribe(&format!("user:{user_id}"))
+        .await
+    {
+        Ok(subscription) => subscription,
+        Err(e) => {
+            error!("failed to subscribe to the events of user {user_id}: {e:?}");
+            return;
+        }
+    };
+    let connection_id = match next_connection_id(&state).await {
+        Ok(connection_id) => connection_id,
+        Err(e) => {
+            error!("failed to allocate a connection id: {e:?}");
+            return;
+        }
+    };
+    if let Err(e) = mark_user_socket(&state, user_id, connection_id).await {
+        error!("failed to mark the user socket of user {user_id}: {e:?}");
+    }
+
+    let (tx, mut rx_ws) = socket.split();
+    let tx = Arc::new(Mutex::new(tx));
+
+    // Finishes when the session is revoked, which closes the socket.
+    let forward_tx = tx.clone();
+    let mut forwarder = tokio::spawn(async move {
+        while let Some(text) = subscription.recv().await {
+            let is_revoked = UserControlEvent::revokes(&text, &session_id);
+            let mut tx_guard = forward_tx.lock().await;
+            if tx_guard.send(Message::Text(text.into())).await.is_err() {
+                break;
+            }
+            if is_revoked {
+                let _ = tx_guard.send(Message::Close(None)).await;
+                break;
+            }
+        }
+    });
+
+    let mut heartbeat = interval_at(Instant::now() + HEARTBEAT_INTERVAL, HEARTBEAT_INTERVAL);
+    loop {
+        tokio::select! {
+            frame = rx_ws.next() => {
+                let Some(Ok(frame)) = frame else { break };
+                if let Message::Text(text) = frame {
+                    handle_user_frame(&state, user_id, &tx, &text).await;
+                }
+            }
+            _ = heartbeat.tick() => {
+                if let Err(e) = mark_user_socket(&state, user_id, connection_id).await {
+                    error!("failed to mark the user socket of user {user_id}: {e:?}");
+                }
+            }
+            _ = &mut forwarder => break,
+        }
+    }
+
+    forwarder.abort();
+    if let Err(e) = clear_user_socket(&state, user_id, connection_id).await {
+        error!("failed to clear the user socket of user {user_id}: {e:?}");
+    }
+}
+
+async fn handle_user_frame(state: &AppState, user_id: i32, tx: &UserSink, text: &str) {
+    let result = match serde_json::from_str::<UserFrame>(text) {
+        Ok(UserFrame::SetStatus {
+            status,
+            status_message,
+        }) => update_status(state, user_id, status, status_message)
+            .await
+            .map(|_| ()),
+        Err(e) => {
+            error!("Unexpected error in handling user frames: {e}");
+            return;
+        }
+    };
+
+    if let Err(e) = result {
+        let error = match e {
+            Error::BadRequest(msg) => msg,
+            e => {
+                error!("failed to update status of user {user_id}: {e:?}");
+                "something went wrong"
+            }
+        };
+        let event = OutgoingMessage::Error {
+            chat_id: None,
+            error: error.to_string(),
+        };
+        if let Ok(payload) = serde_json::to_string(&event) {
+            let _ = tx.lock().await.send(Message::Text(payload.into())).await;
+        }
+    }
+}
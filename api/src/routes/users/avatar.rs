@@ -0,0 +1,90 @@
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::{DefaultBodyLimit, State},
+    http::StatusCode,
+};
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set};
+use tracing::error;
+
+use crate::{
+    AppState,
+    entity::user,
+    errors::Error,
+    models::{claims::Claims, user::UserProfile},
+    routes::chat::refresh_user_lists,
+};
+
+use super::profile::find_user;
+
+pub const MAX_AVATAR_BYTES: usize = 1024 * 1024;
+
+/// Lets avatar uploads through axum's default body limit; the handler enforces
+/// `MAX_AVATAR_BYTES` itself.
+pub fn avatar_body_limit() -> DefaultBodyLimit {
+    DefaultBodyLimit::max(MAX_AVATAR_BYTES + 1)
+}
+
+/// Replaces the caller's avatar with the image in the raw request body. The
+/// format is sniffed from the bytes rather than taken from `Content-Type`.
+pub async fn upload_avatar(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<UserProfile>), Error> {
+    if body.is_empty() {
+        return Err(Error::BadRequest("avatar is empty"));
+    }
+    if body.len() > MAX_AVATAR_BYTES {
+        return Err(Error::BadRequest("avatar is too large"));
+    }
+    let extension = image_extension(&body)
+        .ok_or(Error::BadRequest("avatar must be a png, jpeg, gif or webp"))?;
+
+    let previous = find_user(&state, claims.sub).await?;
+
+    let key = format!(
+        "avatars/{}-{}.{extension}",
+        claims.sub,
+        Utc::now().timestamp_millis()
+    );
+    let url = state.blob_store.put(&key, body.to_vec()).await?;
+
+    let user_row = user::ActiveModel {
+        id: Set(claims.sub),
+        avatar_key: Set(Some(key)),
+        avatar_url: Set(Some(url)),
+        ..Default::default()
+    }
+    .update(&state.db)
+    .await?;
+
+    if let Some(old_key) = previous.avatar_key {
+        delete_avatar(&state, &old_key).await;
+    }
+    refresh_user_lists(&state, user_row.id).await;
+
+    Ok((StatusCode::OK, Json(UserProfile::own(user_row))))
+}
+
+/// The new avatar is already in place, so a leftover file is only logged.
+async fn delete_avatar(state: &AppState, key: &str) {
+    if let Err(e) = state.blob_store.delete(key).await {
+        error!("failed to delete old avatar {key}: {e:?}");
+    }
+}
+
+fn image_extension(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("gif")
+    } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("webp")
+    } else {
+        None
+    }
+}
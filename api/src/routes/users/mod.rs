@@ -0,0 +1,7 @@
+mod avatar;
+mod profile;
+mod status;
+
+pub use avatar::{avatar_body_limit, upload_avatar};
+pub use profile::{get_my_profile, get_user, update_my_profile};
+pub use status::{set_status, update_status};
@@ -0,0 +1,152 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, EntityTrait, SqlErr};
+
+use crate::{
+    AppState,
+    entity::user,
+    errors::{Error, FieldError},
+    models::{
+        claims::Claims,
+        user::{UpdateProfileRequest, UserProfile},
+    },
+    routes::{
+        auth::{check_email, email_taken_error, invalidate_reset_tokens, verify_password},
+        chat::{is_online, refresh_user_lists},
+    },
+};
+
+const MAX_DISPLAY_NAME_CHARS: usize = 50;
+const MAX_BIO_CHARS: usize = 500;
+
+pub async fn get_user(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<(StatusCode, Json<UserProfile>), Error> {
+    let user_row = find_user(&state, id).await?;
+
+    if user_row.id == claims.sub {
+        return Ok((StatusCode::OK, Json(UserProfile::own(user_row))));
+    }
+
+    let online = is_online(&state, id).await?;
+    Ok((StatusCode::OK, Json(UserProfile::public(user_row, online))))
+}
+
+pub async fn get_my_profile(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<UserProfile>), Error> {
+    let user_row = find_user(&state, claims.sub).await?;
+
+    Ok((StatusCode::OK, Json(UserProfile::own(user_row))))
+}
+
+pub async fn update_my_profile(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateProfileRequest>,
+) -> Result<(StatusCode, Json<UserProfile>), Error> {
+    let mut changes = user::ActiveModel {
+        id: Set(claims.sub),
+        ..Default::default()
+    };
+
+    if let Some(display_name) = payload.display_name {
+        let display_name = normalize(display_name);
+        if let Some(name) = &display_name {
+            if name.chars().count() > MAX_DISPLAY_NAME_CHARS {
+                return Err(Error::BadRequest("display name too long"));
+            }
+            if name.chars().any(char::is_control) {
+                return Err(Error::BadRequest(
+                    "display name contains invalid characters",
+                ));
+            }
+        }
+        changes.display_name = Set(display_name);
+    }
+
+    if let Some(bio) = payload.bio {
+        let bio = normalize(bio);
+        if bio
+            .as_ref()
+            .is_some_and(|bio| bio.chars().count() > MAX_BIO_CHARS)
+        {
+            return Err(Error::BadRequest("bio too long"));
+        }
+        changes.bio = Set(bio);
+    }
+
+    // The address is where reset links go, so changing it takes the password,
+    // like changing the password itself.
+    let mut email_changed = false;
+    if let Some(email) = payload.email {
+        let email = normalize(email);
+        if let Some(error) = email.as_deref().and_then(check_email) {
+            return Err(Error::Validation(vec![error]));
+        }
+
+        let user_row = find_user(&state, claims.sub).await?;
+        if email != user_row.email {
+            let Some(current_password) = payload.current_password.as_deref() else {
+                return Err(Error::Validation(vec![FieldError::new(
+                    "currentPassword",
+                    "required",
+                    "is needed to change the email address",
+                )]));
+            };
+            if !verify_password(current_password, &user_row.password).await? {
+                return Err(Error::Validation(vec![FieldError::new(
+                    "currentPassword",
+                    "incorrect",
+                    "is not your current password",
+                )]));
+            }
+            email_changed = true;
+        }
+        changes.email = Set(email);
+    }
+
+    let user_row = if changes.is_changed() {
+        let user_row = changes
+            .update(&state.db)
+            .await
+            .map_err(|e| match e.sql_err() {
+                Some(SqlErr::UniqueConstraintViolation(_)) => {
+                    Error::Conflict(vec![email_taken_error()])
+                }
+                _ => e.into(),
+            })?;
+        if email_changed {
+            invalidate_reset_tokens(&state, user_row.id).await?;
+            state
+                .session_client
+                .revoke_all(user_row.id, Some(&claims.jti))
+                .await?;
+        }
+        refresh_user_lists(&state, user_row.id).await;
+        user_row
+    } else {
+        find_user(&state, claims.sub).await?
+    };
+
+    Ok((StatusCode::OK, Json(UserProfile::own(user_row))))
+}
+
+pub(super) async fn find_user(state: &AppState, id: i32) -> Result<user::Model, Error> {
+    user::Entity::find_by_id(id)
+        .one(&state.db)
+        .await?
+        .ok_or(Error::NotFound)
+}
+
+/// Trims the value; blank values clear the field.
+fn normalize(value: String) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
@@ -0,0 +1,63 @@
+use axum::{Extension, Json, extract::State, http::StatusCode};
+use sea_orm::{ActiveModelTrait, ActiveValue::Set};
+
+use crate::{
+    AppState,
+    entity::user::{self, UserStatus},
+    errors::Error,
+    models::{
+        claims::Claims,
+        user::{SetStatusRequest, UserProfile},
+    },
+    routes::chat::broadcast_user_status,
+};
+
+const MAX_STATUS_MESSAGE_CHARS: usize = 100;
+
+pub async fn set_status(
+    Extension(claims): Extension<Claims>,
+    State(state): State<AppState>,
+    Json(payload): Json<SetStatusRequest>,
+) -> Result<(StatusCode, Json<UserProfile>), Error> {
+    let user_row =
+        update_status(&state, claims.sub, payload.status, payload.status_message).await?;
+
+    Ok((StatusCode::OK, Json(UserProfile::own(user_row))))
+}
+
+/// Stores the status the user chose and broadcasts it. `offline` is only ever
+/// derived from presence; users who want to look offline pick `invisible`.
+pub async fn update_status(
+    state: &AppState,
+    user_id: i32,
+    status: UserStatus,
+    status_message: Option<String>,
+) -> Result<user::Model, Error> {
+    if status == UserStatus::Offline {
+        return Err(Error::BadRequest(
+            "status must be online, away, busy or invisible",
+        ));
+    }
+
+    let status_message = status_message
+        .map(|message| message.trim().to_string())
+        .filter(|message| !message.is_empty());
+    if status_message
+        .as_ref()
+        .is_some_and(|message| message.chars().count() > MAX_STATUS_MESSAGE_CHARS)
+    {
+        return Err(Error::BadRequest("status message too long"));
+    }
+
+    let user_row = user::ActiveModel {
+        id: Set(user_id),
+        status: Set(status),
+        status_message: Set(status_message),
+        ..Default::default()
+    }
+    .update(&state.db)
+    .await?;
+
+    broadcast_user_status(state, &user_row).await;
+    Ok(user_row)
+}
@@ -0,0 +1,104 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use jsonwebtoken::{
+    Algorithm, DecodingKey,
+    jwk::{Jwk, JwkSet},
+};
+use tracing::{info, warn};
+
+use crate::core::ServerPool;
+
+/// How often the backends' signing keys are fetched. A key a backend starts
+/// publishing is honoured here at most this long after; the rotation procedure
+/// in the README waits for it before signing with a new key.
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+const JWKS_FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+pub struct VerificationKey {
+    pub algorithm: Algorithm,
+    pub key: DecodingKey,
+}
+
+/// The public keys the backends sign access tokens with, by `kid`, so tokens
+/// can be verified here instead of trusting whatever the cookie says.
+#[derive(Clone, Default)]
+pub struct JwksCache {
+    keys: Arc<RwLock<HashMap<String, VerificationKey>>>,
+}
+
+impl JwksCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, kid: &str) -> Option<VerificationKey> {
+        self.keys.read().ok()?.get(kid).cloned()
+    }
+
+    pub async fn start(&self, server_pool: ServerPool) {
+        let mut interval = tokio::time::interval(JWKS_REFRESH_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.refresh(&server_pool).await;
+        }
+    }
+
+    /// Replaces the cached keys with every key any backend publishes. Backends
+    /// that are mid-rotation may disagree, and a token signed by either must
+    /// verify. If no backend answers, the previous keys are kept.
+    async fn refresh(&self, server_pool: &ServerPool) {
+        let mut keys = HashMap::new();
+        let mut answered = false;
+
+        for server in server_pool.get_servers().await {
+            match self.fetch(server.jwks_url()).await {
+                Ok(set) => {
+                    answered = true;
+                    for jwk in &set.keys {
+                        if let Some((kid, key)) = verification_key(jwk) {
+                            keys.insert(kid, key);
+                        }
+                    }
+                }
+                Err(e) => warn!("Failed to fetch JWKS from {}: {}", server.id, e),
+            }
+        }
+
+        if !answered {
+            return;
+        }
+        let Ok(mut cached) = self.keys.write() else {
+            return;
+        };
+        if cached.len() != keys.len() || keys.keys().any(|kid| !cached.contains_key(kid)) {
+            info!("JWT verification keys updated: {:?}", keys.keys());
+        }
+        *cached = keys;
+    }
+
+    async fn fetch(&self, url: String) -> Result<JwkSet, Box<dyn std::error::Error>> {
+        let response = tokio::time::timeout(JWKS_FETCH_TIMEOUT, reqwest::get(url)).await??;
+        Ok(response.error_for_status()?.json().await?)
+    }
+}
+
+/// Only asymmetric keys are accepted; a symmetric one would let anyone who can
+/// read the key set sign tokens.
+fn verification_key(jwk: &Jwk) -> Option<(String, VerificationKey)> {
+    let kid = jwk.common.key_id.clone()?;
+    let algorithm = Algorithm::from_str(&jwk.common.key_algorithm?.to_string()).ok()?;
+    if matches!(
+        algorithm,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        return None;
+    }
+    let key = DecodingKey::from_jwk(jwk).ok()?;
+    Some((kid, VerificationKey { algorithm, key }))
+}
@@ -1,7 +1,9 @@
 mod health_checker;
+mod jwks_cache;
 mod server_pool;
 mod websocket_manager;
 
 pub use health_checker::HealthChecker;
+pub use jwks_cache::JwksCache;
 pub use server_pool::{BackendServer, ServerPool};
 pub use websocket_manager::{ConnectionHandle, WebSocketManager};
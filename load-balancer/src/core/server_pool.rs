@@ -17,6 +17,10 @@ impl BackendServer {
     pub fn health_check(&self) -> String {
         format!("{}/health", self.address)
     }
+
+    pub fn jwks_url(&self) -> String {
+        format!("{}/.well-known/jwks.json", self.address)
+    }
 }
 
 #[derive(Clone)]
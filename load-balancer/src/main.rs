@@ -54,6 +54,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
+    let jwks = app_state.proxy_service.jwks().clone();
+    let server_pool = app_state.server_pool.clone();
+    tokio::spawn(async move { jwks.start(server_pool).await });
+
     let public = routes::public_router();
 
     let governor_conf = Arc::new(
@@ -1,5 +1,5 @@
 use crate::config::LoadBalancerConfig;
-use crate::core::{BackendServer, ConnectionHandle, ServerPool, WebSocketManager};
+use crate::core::{BackendServer, ConnectionHandle, JwksCache, ServerPool, WebSocketManager};
 use crate::errors::Error;
 use crate::routing::{extract_server_from_cookie, extract_user_id_from_jwt};
 
@@ -23,6 +23,7 @@ use tracing::{debug, error, info, warn};
 #[derive(Clone)]
 pub struct ProxyService {
     client: Client,
+    jwks: JwksCache,
 }
 
 impl Default for ProxyService {
@@ -41,7 +42,15 @@ impl ProxyService {
             .build()
             .expect("Failed to create HTTP client");
 
-        Self { client }
+        Self {
+            client,
+            jwks: JwksCache::new(),
+        }
+    }
+
+    /// The keys session tokens are verified with before routing by user.
+    pub fn jwks(&self) -> &JwksCache {
+        &self.jwks
     }
 
     pub async fn select_target_server(
@@ -62,7 +71,7 @@ impl ProxyService {
         }
 
         // 2. Try to extract user ID from JWT token for consistent hashing
-        if let Some(user_id) = extract_user_id_from_jwt(cookies)
+        if let Some(user_id) = extract_user_id_from_jwt(cookies, &self.jwks)
             && let Some(server) = self.get_server_for_user(&user_id, server_pool).await
         {
             debug!("Using user-based routing: {} -> {}", user_id, server.id);
@@ -247,7 +256,7 @@ impl ProxyService {
         let lb_secret = config.lb_secret.clone();
         let ws_manager_clone = ws_manager.clone();
 
-        let user_id = extract_user_id_from_jwt(cookies).map(|id| id.to_string());
+        let user_id = extract_user_id_from_jwt(cookies, &self.jwks).map(|id| id.to_string());
 
         let session_cookie = cookies
             .get("session")
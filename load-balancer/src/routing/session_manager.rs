@@ -1,7 +1,10 @@
-use crate::{config::LoadBalancerConfig, core::BackendServer};
+use crate::{
+    config::LoadBalancerConfig,
+    core::{BackendServer, JwksCache},
+};
 
-use base64::Engine;
-use serde_json::Value;
+use jsonwebtoken::{Validation, decode, decode_header};
+use serde::Deserialize;
 use tower_cookies::Cookies;
 use tracing::debug;
 
@@ -16,36 +19,34 @@ pub fn extract_server_from_cookie(
     })
 }
 
-pub fn extract_user_id_from_jwt(cookies: &Cookies) -> Option<String> {
+pub fn extract_user_id_from_jwt(cookies: &Cookies, jwks: &JwksCache) -> Option<String> {
     let token_cookie_name = "session";
     if let Some(cookie) = cookies.get(token_cookie_name)
-        && let Some(user_id) = parse_jwt_user_id(cookie.value())
+        && let Some(user_id) = verify_jwt_user_id(cookie.value(), jwks)
     {
         debug!("Extracted user ID from JWT: {}", user_id);
         return Some(user_id);
     }
-    debug!("No JWT token found in cookies");
+    debug!("No verified JWT token found in cookies");
     None
 }
 
-pub fn parse_jwt_user_id(token: &str) -> Option<String> {
-    let parts: Vec<&str> = token.split('.').collect();
-    if parts.len() != 3 {
-        return None;
-    }
-
-    let mut payload_b64 = parts[1].to_string();
-    while !payload_b64.len().is_multiple_of(4) {
-        payload_b64.push('=');
-    }
-    let payload_b64 = payload_b64.replace('-', "+").replace('_', "/");
+#[derive(Deserialize)]
+struct SessionClaims {
+    sub: u64,
+}
 
-    let payload_bytes =
-        Engine::decode(&base64::engine::general_purpose::STANDARD, &payload_b64).ok()?;
-    let payload_str = String::from_utf8(payload_bytes).ok()?;
-    let payload: Value = serde_json::from_str(&payload_str).ok()?;
+/// The user id of a token signed by one of the backends' keys that has not
+/// expired. Anything else is treated as anonymous.
+pub fn verify_jwt_user_id(token: &str, jwks: &JwksCache) -> Option<String> {
+    let kid = decode_header(token).ok()?.kid?;
+    let Some(key) = jwks.get(&kid) else {
+        debug!("JWT signed with unknown key: {}", kid);
+        return None;
+    };
 
-    payload.get("sub")?.as_u64().map(|id| id.to_string())
+    let data = decode::<SessionClaims>(token, &key.key, &Validation::new(key.algorithm)).ok()?;
+    Some(data.claims.sub.to_string())
 }
 
 /// Set sticky session cookie so future requests go to same server
@@ -1,17 +1,26 @@
 import { request } from "@api/request";
 
 export type Message = {
+  id: number;
+  chatId: number;
+  senderId: number;
   username: string;
   content: string;
+  createdAt: string;
+  editedAt?: string | null;
+  deletedAt?: string | null;
+  parentId?: number | null;
+  replyCount?: number;
+  reactions?: { emoji: string; count: number; reacted: boolean }[];
 };
 
 export type CreateChat = {
-  ownerId: number;
   name: string;
 };
 
 export type Chat = CreateChat & {
   id: number;
+  ownerId: number;
   createdAt: Date;
   activeUsers: number;
   visibility: "public" | "private";
@@ -36,6 +45,9 @@ export const loadChatList = async () => {
 };
 
 export const loadChatListByName = async (name: string) => {
-  const response = await request.get<Chat[]>(`chat/name/${name}`);
-  return response.data;
+  const response = await request.get<{ chats: Chat[]; total: number }>(
+    "chat/search",
+    { params: { q: name } }
+  );
+  return response.data.chats;
 };
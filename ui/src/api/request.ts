@@ -21,6 +21,60 @@ instance.interceptors.response.use(
   (error) => Promise.reject(error)
 );
 
+// Access tokens are short-lived: on a 401, trade the refresh cookie for a new
+// pair once and replay the request. A refresh token can only be used once and
+// every tab shares the cookie, so refreshes are serialized across tabs with a
+// Web Lock, and a tab skips its own refresh when another tab refreshed after
+// the failed request was sent.
+const REFRESH_LOCK = "auth-refresh";
+const authChannel =
+  typeof BroadcastChannel === "undefined" ? null : new BroadcastChannel("auth");
+let lastRefreshAt = 0;
+let refreshing: Promise<unknown> | null = null;
+
+authChannel?.addEventListener("message", (event) => {
+  if (event.data?.type === "refreshed") {
+    lastRefreshAt = Math.max(lastRefreshAt, event.data.at);
+  }
+});
+
+const refreshOnce = async (sentAt: number) => {
+  if (lastRefreshAt > sentAt) return;
+  await instance.post("refresh");
+  lastRefreshAt = Date.now();
+  authChannel?.postMessage({ type: "refreshed", at: lastRefreshAt });
+};
+
+const refreshAcrossTabs = (sentAt: number) =>
+  navigator.locks
+    ? navigator.locks.request(REFRESH_LOCK, () => refreshOnce(sentAt))
+    : refreshOnce(sentAt);
+
+instance.interceptors.request.use((config) => {
+  (config as typeof config & { _sentAt?: number })._sentAt = Date.now();
+  return config;
+});
+
+instance.interceptors.response.use(undefined, async (error) => {
+  const config = error.config;
+  const isAuthCall = ["login", "register", "refresh"].includes(config?.url);
+  if (error.response?.status !== 401 || !config || config._retried || isAuthCall) {
+    return Promise.reject(error);
+  }
+
+  refreshing ??= refreshAcrossTabs(config._sentAt ?? 0).finally(() => {
+    refreshing = null;
+  });
+
+  try {
+    await refreshing;
+  } catch {
+    return Promise.reject(error);
+  }
+  config._retried = true;
+  return instance(config);
+});
+
 const externalInstance = axios.create();
 
 export const request = instance;
@@ -13,6 +13,7 @@ import { useAuth } from "@hooks/auth-context";
 import { useWebSocket } from "@api/use-websocket";
 import { useLoadChat } from "@api/chat/hooks";
 import { Spinner } from "@components/spinner";
+import type { Message as ChatMessage } from "@api/chat/request";
 
 type User = {
   id: string;
@@ -32,26 +33,56 @@ type Message = {
   systemType?: "join" | "leave" | "info";
 };
 
-type WSData = {
-  type:
-    | "message"
-    | "user_list"
-    | "system_message"
-    | "suggestion"
-    | "suggestion_error";
-  subtype?: "join" | "leave";
-  content: string | string[];
-  username?: string;
-  text?: string;
-  error?: string;
-};
+type WSData =
+  | ({ type: "message" } & ChatMessage)
+  | {
+      type: "system_message";
+      subtype: "join" | "leave";
+      chatId: number;
+      userId: number;
+      username: string;
+      content: string;
+      createdAt: string;
+    }
+  | {
+      type: "user_list";
+      chatId: number;
+      users: {
+        id: number;
+        username: string;
+        status: "online" | "away" | "busy";
+        statusMessage: string | null;
+        lastSeenAt: string | null;
+      }[];
+    }
+  | {
+      type: "message_edited";
+      id: number;
+      chatId: number;
+      content: string;
+      editedAt: string;
+    }
+  | { type: "message_deleted"; id: number; chatId: number; deletedAt: string }
+  | { type: "resync"; chatId: number }
+  | { type: "suggestion"; text: string }
+  | { type: "suggestion_error"; error: string }
+  | { type: "error"; error: string };
+
+const toMessage = (m: ChatMessage): Message => ({
+  id: String(m.id),
+  userId: String(m.senderId),
+  username: m.username,
+  content: m.content,
+  createdAt: m.createdAt,
+  isSystem: false,
+});
 
 export const ChatRoom = ({ onBack }: { onBack?: () => void }) => {
   const { roomId = "" } = useParams<{ roomId: string }>();
   const { user } = useAuth();
   const navigate = useNavigate();
 
-  const { data: room, isLoading } = useLoadChat(Number(roomId));
+  const { data: room, isLoading, refetch } = useLoadChat(Number(roomId));
 
   const [users, setUsers] = useState<User[]>([]);
   const [messages, setMessages] = useState<Message[]>([]);
@@ -103,22 +134,23 @@ export const ChatRoom = ({ onBack }: { onBack?: () => void }) => {
 
         switch (data.type) {
           case "message": {
-            const content = data.content as string;
-            const colonIndex = content.indexOf(":");
-            if (colonIndex !== -1) {
-              const username = content.substring(0, colonIndex);
-              const messageContent = content.substring(colonIndex + 1).trim();
-
-              const newMessage: Message = {
-                id: `msg-${Date.now()}-${Math.random().toString(36).slice(2)}`,
-                userId: username === user?.username ? user.username : username,
-                username,
-                content: messageContent,
-                createdAt: new Date().toISOString(),
-                isSystem: false,
-              };
-              setMessages((prev) => [...prev, newMessage]);
-            }
+            // Thread replies are not shown in the main timeline
+            if (data.parentId) break;
+            setMessages((prev) => [...prev, toMessage(data)]);
+            break;
+          }
+
+          case "message_edited": {
+            setMessages((prev) =>
+              prev.map((m) =>
+                m.id === String(data.id) ? { ...m, content: data.content } : m
+              )
+            );
+            break;
+          }
+
+          case "message_deleted": {
+            setMessages((prev) => prev.filter((m) => m.id !== String(data.id)));
             break;
           }
 
@@ -127,20 +159,31 @@ export const ChatRoom = ({ onBack }: { onBack?: () => void }) => {
               id: `sys-${Date.now()}-${Math.random().toString(36).slice(2)}`,
               userId: "system",
               username: "System",
-              content: data.content as string,
-              createdAt: new Date().toISOString(),
+              content: data.content,
+              createdAt: data.createdAt,
               isSystem: true,
-              systemType: data.subtype || "info",
+              systemType: data.subtype,
             };
             setMessages((prev) => [...prev, systemMessage]);
             break;
           }
 
+          case "resync": {
+            // Some events were missed, so the timeline is reloaded instead
+            refetch().then(({ data: fresh }) => {
+              if (fresh?.messages) setMessages(fresh.messages.map(toMessage));
+            });
+            break;
+          }
+
           case "user_list": {
-            const userList = [...new Set(data.content as string[])];
-            const newUsers: User[] = userList.map((username) => ({
-              id: username,
-              name: username,
+            const seen = new Set<number>();
+            const userList = data.users.filter(
+              (u) => !seen.has(u.id) && seen.add(u.id)
+            );
+            const newUsers: User[] = userList.map((u) => ({
+              id: String(u.id),
+              name: u.username,
               avatar: undefined,
               role: "member",
               online: true,
@@ -150,7 +193,7 @@ export const ChatRoom = ({ onBack }: { onBack?: () => void }) => {
           }
 
           case "suggestion": {
-            setSuggestion(data.text || "");
+            setSuggestion(data.text);
             setSuggestionVisible(true);
             break;
           }
@@ -161,6 +204,11 @@ export const ChatRoom = ({ onBack }: { onBack?: () => void }) => {
             break;
           }
 
+          case "error": {
+            console.error("Chat error:", data.error);
+            break;
+          }
+
           default:
             console.log("Unknown message type:", data);
         }
@@ -235,28 +283,7 @@ export const ChatRoom = ({ onBack }: { onBack?: () => void }) => {
     // if we already hydrated this room, do nothing
     if (hydratedRoomRef.current === roomId) return;
 
-    // build message objects from server history
-    const now = Date.now();
-    const history = room.messages.map(
-      (
-        m: {
-          username?: string;
-          name?: string;
-          content: string;
-          createdAt?: string;
-        },
-        i: number
-      ) => ({
-        id: `hist-${i}-${now}`,
-        userId: m.username ?? m.name ?? "unknown",
-        username: m.username ?? m.name ?? "unknown",
-        content: m.content,
-        createdAt:
-          m.createdAt ??
-          new Date(now - (room.messages.length - 1 - i) * 1000).toISOString(),
-        isSystem: false as const,
-      })
-    );
+    const history = room.messages.map(toMessage);
 
     // only hydrate if we don't already have live messages
     setMessages((prev) => (prev.length > 0 ? prev : history));
@@ -419,20 +446,25 @@ export const ChatRoom = ({ onBack }: { onBack?: () => void }) => {
                           );
                         }
 
-                        const isOwn = run.userId === user.username;
+                        const isOwn = run.userId === String(user.id);
 
                         return (
                           <MessageRun key={firstMessage.id}>
                             <AvatarWrapper>
                               <Avatar
-                                user={u || { id: run.userId, name: run.userId }}
+                                user={
+                                  u || {
+                                    id: run.userId,
+                                    name: firstMessage.username,
+                                  }
+                                }
                                 size={36}
                               />
                             </AvatarWrapper>
                             <MessageCol>
                               <RunHeader>
                                 <strong>
-                                  {isOwn ? "You" : u?.name || run.userId}
+                                  {isOwn ? "You" : u?.name || firstMessage.username}
                                 </strong>
                                 <RunTime>
                                   {new Date(
@@ -192,7 +192,6 @@ export const HomePage = () => {
     try {
       const { id } = await createChat.mutateAsync({
         name: name.trim(),
-        ownerId: user.id,
       });
       navigate(`/chat/${id}`);
     } catch (error) {